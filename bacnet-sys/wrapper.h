@@ -16,4 +16,17 @@
 #include "bacnet-stack-1.0.0/src/bacnet/version.h"
 #include "bacnet-stack-1.0.0/src/bacnet/datalink/dlenv.h"
 #include "bacnet-stack-1.0.0/src/bacnet/bacenum.h"
+#include "bacnet-stack-1.0.0/src/bacnet/datetime.h"
+#include "bacnet-stack-1.0.0/src/bacnet/timesync.h"
+#include "bacnet-stack-1.0.0/src/bacnet/dcc.h"
+#include "bacnet-stack-1.0.0/src/bacnet/arf.h"
+#include "bacnet-stack-1.0.0/src/bacnet/awf.h"
+#include "bacnet-stack-1.0.0/src/bacnet/timestamp.h"
+#include "bacnet-stack-1.0.0/src/bacnet/getevent.h"
+#include "bacnet-stack-1.0.0/src/bacnet/get_alarm_sum.h"
+#include "bacnet-stack-1.0.0/src/bacnet/alarm_ack.h"
+#include "bacnet-stack-1.0.0/src/bacnet/event.h"
+#include "bacnet-stack-1.0.0/src/bacnet/abort.h"
+#include "bacnet-stack-1.0.0/src/bacnet/reject.h"
+#include "bacnet-stack-1.0.0/src/bacnet/ptransfer.h"
 //#include "bacnet-stack-1.0.0/src/bacnet/bacport.h"
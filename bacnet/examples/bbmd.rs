@@ -0,0 +1,44 @@
+use clap::Parser;
+use std::net::SocketAddrV4;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Opt {
+    /// B/IP address of the BBMD, e.g. 192.168.10.1:47808
+    bbmd: SocketAddrV4,
+
+    /// Read the Foreign Device Table instead of the Broadcast Distribution Table
+    #[arg(long)]
+    fdt: bool,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let opt = Opt::parse();
+
+    let bbmd = bacnet::bbmd::Bbmd::new(opt.bbmd);
+    if opt.fdt {
+        match bbmd.read_fdt() {
+            Ok(entries) => {
+                for entry in entries {
+                    println!(
+                        "{:21}  TTL={:5}  remaining={:5}",
+                        entry.address.to_string(),
+                        entry.ttl,
+                        entry.remaining
+                    );
+                }
+            }
+            Err(err) => eprintln!("Read-FDT failed: {}", err),
+        }
+    } else {
+        match bbmd.read_bdt() {
+            Ok(entries) => {
+                for entry in entries {
+                    println!("{:21}  mask={}", entry.address.to_string(), entry.mask);
+                }
+            }
+            Err(err) => eprintln!("Read-BDT failed: {}", err),
+        }
+    }
+}
@@ -16,27 +16,50 @@ struct Opt {
     dadr: u8,
     #[arg(long, default_value_t = 47808)]
     port: u16,
+    /// Show the values of volatile properties instead of `?`
+    #[arg(short = 'v', long)]
+    show_values: bool,
 }
 
 fn main() {
     pretty_env_logger::init();
     let opt = Opt::parse();
-    let mut dev = BACnetDevice::builder()
+    let mut builder = BACnetDevice::builder()
         .device_id(opt.device_id)
         .ip(opt.ip)
-        .dnet(opt.dnet)
-        .dadr(opt.dadr)
-        .port(opt.port)
-        .build();
+        .port(opt.port);
+    if opt.dnet != 0 {
+        builder = builder.dnet(opt.dnet).dadr(opt.dadr);
+    }
+    let mut dev = match builder.build() {
+        Ok(dev) => dev,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
 
-    println!("{:?}", dev);
+    eprintln!("{:?}", dev);
     match dev.connect() {
-        Ok(()) => match dev.epics() {
-            Ok(epics) => {
-                println!("Got epics {:#?}", epics);
+        Ok(()) => {
+            let mut interrogation = dev.interrogate().on_progress(|progress| {
+                eprint!(
+                    "\r{:?} {}/{}",
+                    progress.phase, progress.done, progress.total
+                );
+            });
+            let result = interrogation.run();
+            eprintln!();
+            for error in interrogation.errors() {
+                eprintln!("failed to read {}", error);
             }
-            Err(err) => eprintln!("failed to read property: {}", err),
-        },
+            match result {
+                Ok(epics) => {
+                    print!("{}", epics.text().show_values(opt.show_values));
+                }
+                Err(err) => eprintln!("failed to read property: {}", err),
+            }
+        }
         Err(err) => {
             eprintln!("failed to connect to device... {}", err);
         }
@@ -0,0 +1,33 @@
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Opt {
+    /// How long to listen for event notifications (in seconds)
+    #[arg(long, default_value_t = 60)]
+    listen: u64,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let opt = Opt::parse();
+
+    let notifications = bacnet::event::subscribe();
+    bacnet::poll(std::time::Duration::from_secs(opt.listen));
+    for n in notifications.try_iter() {
+        println!(
+            "device {}: object {}:{} {:?} -> {:?} (class {}, priority {}) {}",
+            n.initiating_device,
+            n.object_type,
+            n.object_instance,
+            n.from_state,
+            n.to_state,
+            n.notification_class,
+            n.priority,
+            n.message_text.as_deref().unwrap_or("")
+        );
+        if let Some(values) = n.event_values {
+            println!("    {:?}", values);
+        }
+    }
+}
@@ -69,13 +69,20 @@ fn parse_property(src: &str) -> Result<bacnet_sys::BACNET_PROPERTY_ID, String> {
 fn main() {
     pretty_env_logger::init();
     let opt = Opt::parse();
-    let mut dev = BACnetDevice::builder()
+    let mut builder = BACnetDevice::builder()
         .device_id(opt.device_id)
         .ip(opt.ip)
-        .dnet(opt.dnet)
-        .dadr(opt.dadr)
-        .port(opt.port)
-        .build();
+        .port(opt.port);
+    if opt.dnet != 0 {
+        builder = builder.dnet(opt.dnet).dadr(opt.dadr);
+    }
+    let mut dev = match builder.build() {
+        Ok(dev) => dev,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
 
     println!("{:?}", dev);
     match dev.connect() {
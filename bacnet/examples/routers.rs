@@ -0,0 +1,22 @@
+use bacnet::router::WhoIsRouter;
+
+fn main() {
+    pretty_env_logger::init();
+    let networks = WhoIsRouter::new()
+        .timeout(std::time::Duration::from_secs(3))
+        .execute();
+
+    println!("DNET            Router MAC          SNET            SADR            Status");
+    println!("----  ------------------------  ----  ------------------------  ------");
+    for route in networks.routes() {
+        println!(
+            "{:4}  {:02X?}  {:4}  {:02X?}  {:?}",
+            route.dnet, route.mac_addr, route.network_number, route.addr, route.status
+        );
+    }
+    println!(
+        "Total: {} network{}",
+        networks.len(),
+        if networks.len() == 1 { "" } else { "s" }
+    );
+}
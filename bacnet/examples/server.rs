@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bacnet::server::{self, LocalObject};
+use bacnet::value::BACnetValue;
+use bacnet::{ErrorClass, ErrorCode};
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Opt {
+    #[arg(long, default_value_t = 1234)]
+    device_id: u32,
+    #[arg(long, default_value = "Rust gateway")]
+    name: String,
+    /// How long to keep answering requests (in seconds)
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let opt = Opt::parse();
+
+    let started = Instant::now();
+    let fan = Arc::new(AtomicBool::new(false));
+    let fan_get = fan.clone();
+
+    server::add_object(
+        LocalObject::new(
+            bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_INPUT,
+            1,
+            "Uptime",
+        )
+        .getter(
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+            move || BACnetValue::Real(started.elapsed().as_secs_f32()),
+        )
+        .value(
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_UNITS,
+            BACnetValue::Enum(bacnet_sys::BACNET_ENGINEERING_UNITS_UNITS_SECONDS, None),
+        )
+        .value(
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_STATUS_FLAGS,
+            BACnetValue::BitString(vec![false; 4]),
+        ),
+    )
+    .unwrap();
+    server::add_object(
+        LocalObject::new(
+            bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_VALUE,
+            1,
+            "Setpoint",
+        )
+        .writable_value(
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+            BACnetValue::Real(21.0),
+        ),
+    )
+    .unwrap();
+    server::add_object(
+        LocalObject::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_VALUE, 1, "Fan").property(
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+            move || BACnetValue::Enum(fan_get.load(Ordering::SeqCst) as u32, None),
+            move |value, priority| match value {
+                BACnetValue::Enum(state, _) if state <= 1 => {
+                    println!("fan {} (priority {})", state, priority);
+                    fan.store(state == 1, Ordering::SeqCst);
+                    Ok(())
+                }
+                _ => Err((ErrorClass::Property, ErrorCode::InvalidDataType)),
+            },
+        ),
+    )
+    .unwrap();
+
+    server::start(opt.device_id, &opt.name).unwrap();
+    bacnet::poll(Duration::from_secs(opt.duration));
+    println!(
+        "setpoint: {:?}",
+        server::value(
+            bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_VALUE,
+            1,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE
+        )
+    );
+}
@@ -0,0 +1,35 @@
+use bacnet::timesync::TimeSync;
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Opt {
+    /// Send UTC-Time-Synchronization instead of Time-Synchronization
+    #[arg(long)]
+    utc: bool,
+    /// Broadcast to this network instead of the local network
+    #[arg(long)]
+    dnet: Option<u16>,
+    /// Keep listening for time synchronization requests from other devices (in seconds)
+    #[arg(long, default_value_t = 0)]
+    listen: u64,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let opt = Opt::parse();
+
+    let requests = bacnet::timesync::subscribe();
+    TimeSync::now().utc(opt.utc).subnet(opt.dnet).send();
+
+    bacnet::poll(std::time::Duration::from_secs(opt.listen));
+    for req in requests.try_iter() {
+        println!(
+            "{} time synchronization from {:02X?} (net {}): {}",
+            if req.utc { "UTC" } else { "Local" },
+            req.mac_addr,
+            req.network_number,
+            req.time
+        );
+    }
+}
@@ -9,12 +9,14 @@ fn main() {
         .unwrap();
 
     let ndevices = devices.len();
-    println!("Device ID             MAC            SNET            SADR            APDU");
-    println!("---------  ------------------------  ----  ------------------------  ----");
+    println!("Device ID  Address                          APDU");
+    println!("---------  -------------------------------  ----");
     for dev in devices {
         println!(
-            "{:9}  {:02X?}  {:4}  {:02X?}  {:4}",
-            dev.device_id, dev.mac_addr, dev.network_number, dev.addr, dev.max_apdu
+            "{:9}  {:31}  {:4}",
+            dev.device_id,
+            dev.address.to_string(),
+            dev.max_apdu
         );
     }
     println!(
@@ -0,0 +1,392 @@
+//! BACnet addresses, and their notation as strings
+//!
+//! The notations are:
+//!
+//! - `192.168.10.96:47808` (or just `192.168.10.96`): a B/IP device on the local network
+//! - `[fe80::1]:47808`: a B/IPv6 device on the local network (the port is required)
+//! - `0x0E`: a device with a non-IP MAC address on the local network
+//! - `5:0x0E` or `5:14`: device 0x0E on network 5, reached through whichever router serves it
+//! - `5:0x0E@192.168.10.1:47808`: device 0x0E on network 5, reached through the given router
+//! - `broadcast`, `local-broadcast` and `5:broadcast`: a global broadcast, a broadcast on the
+//!   local network and a broadcast on network 5
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+const DEFAULT_PORT: u16 = 0xBAC0;
+
+/// The address of a BACnet device, or a broadcast address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BACnetAddress {
+    /// The MAC address of the device, or of the router to reach it through. Empty for broadcasts,
+    /// or when the router isn't known.
+    pub mac: Vec<u8>,
+    /// The network of the device, 0 for the local network and 65535 for a global broadcast.
+    pub net: u16,
+    /// The MAC address of the device on network `net`, empty for the local network and
+    /// broadcasts.
+    pub adr: Vec<u8>,
+}
+
+impl BACnetAddress {
+    /// A B/IP device on the local network
+    pub fn ip(address: SocketAddrV4) -> Self {
+        let mut mac = address.ip().octets().to_vec();
+        mac.extend_from_slice(&address.port().to_be_bytes());
+        BACnetAddress {
+            mac,
+            ..Default::default()
+        }
+    }
+
+    /// A B/IPv6 device on the local network
+    pub fn ipv6(address: SocketAddrV6) -> Self {
+        let mut mac = address.ip().octets().to_vec();
+        mac.extend_from_slice(&address.port().to_be_bytes());
+        BACnetAddress {
+            mac,
+            ..Default::default()
+        }
+    }
+
+    /// A device on network `net` with the MAC address `adr`, through whichever router serves the
+    /// network. Use `via()` to give the router.
+    pub fn remote(net: u16, adr: &[u8]) -> Self {
+        BACnetAddress {
+            mac: vec![],
+            net,
+            adr: adr.to_vec(),
+        }
+    }
+
+    /// Reach the (remote) device through the router with the given address.
+    pub fn via(mut self, router: BACnetAddress) -> Self {
+        self.mac = router.mac;
+        self
+    }
+
+    pub fn global_broadcast() -> Self {
+        BACnetAddress {
+            net: bacnet_sys::BACNET_BROADCAST_NETWORK as u16,
+            ..Default::default()
+        }
+    }
+
+    pub fn local_broadcast() -> Self {
+        BACnetAddress::default()
+    }
+
+    pub fn remote_broadcast(net: u16) -> Self {
+        BACnetAddress::remote(net, &[])
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.net == bacnet_sys::BACNET_BROADCAST_NETWORK as u16
+            || (self.net == 0 && self.mac.is_empty())
+            || (self.net != 0 && self.adr.is_empty())
+    }
+
+    /// The B/IP address of the device or its router, if it's given as one
+    pub fn socket_addr(&self) -> Option<SocketAddrV4> {
+        match self.mac[..] {
+            [a, b, c, d, p0, p1] => Some(SocketAddrV4::new(
+                Ipv4Addr::new(a, b, c, d),
+                u16::from_be_bytes([p0, p1]),
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl From<&bacnet_sys::BACNET_ADDRESS> for BACnetAddress {
+    fn from(addr: &bacnet_sys::BACNET_ADDRESS) -> Self {
+        let mac_len = (addr.mac_len as usize).min(addr.mac.len());
+        let adr_len = (addr.len as usize).min(addr.adr.len());
+        BACnetAddress {
+            mac: addr.mac[..mac_len].to_vec(),
+            net: addr.net,
+            adr: if addr.net > 0 {
+                addr.adr[..adr_len].to_vec()
+            } else {
+                vec![]
+            },
+        }
+    }
+}
+
+impl TryFrom<&BACnetAddress> for bacnet_sys::BACNET_ADDRESS {
+    type Error = Error;
+
+    /// Fails if an address is longer than the stack supports, e.g. a B/IPv6 address when the
+    /// stack is built for B/IP.
+    fn try_from(address: &BACnetAddress) -> Result<Self> {
+        let mut addr = bacnet_sys::BACNET_ADDRESS::default();
+        if address.mac.len() > addr.mac.len() {
+            return Err(Error::InvalidAddress {
+                reason: "the MAC address is too long",
+            });
+        }
+        if address.adr.len() > addr.adr.len() {
+            return Err(Error::InvalidAddress {
+                reason: "the remote address is too long",
+            });
+        }
+        addr.mac_len = address.mac.len() as u8;
+        addr.mac[..address.mac.len()].copy_from_slice(&address.mac);
+        addr.net = address.net;
+        addr.len = address.adr.len() as u8;
+        addr.adr[..address.adr.len()].copy_from_slice(&address.adr);
+        Ok(addr)
+    }
+}
+
+impl fmt::Display for BACnetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.net == bacnet_sys::BACNET_BROADCAST_NETWORK as u16 {
+            return write!(f, "broadcast");
+        }
+        if self.net == 0 {
+            return if self.mac.is_empty() {
+                write!(f, "local-broadcast")
+            } else {
+                write_mac(f, &self.mac)
+            };
+        }
+        write!(f, "{}:", self.net)?;
+        if self.adr.is_empty() {
+            write!(f, "broadcast")?;
+        } else {
+            write_hex(f, &self.adr)?;
+        }
+        if !self.mac.is_empty() {
+            write!(f, "@")?;
+            write_mac(f, &self.mac)?;
+        }
+        Ok(())
+    }
+}
+
+// B/IP and B/IPv6 MAC addresses are written as socket addresses, any other ones in hex
+fn write_mac(f: &mut fmt::Formatter, mac: &[u8]) -> fmt::Result {
+    match mac.len() {
+        6 => {
+            let mut ip = [0u8; 4];
+            ip.copy_from_slice(&mac[..4]);
+            let port = u16::from_be_bytes([mac[4], mac[5]]);
+            write!(f, "{}", SocketAddrV4::new(ip.into(), port))
+        }
+        18 => {
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&mac[..16]);
+            let port = u16::from_be_bytes([mac[16], mac[17]]);
+            write!(f, "{}", SocketAddrV6::new(ip.into(), port, 0, 0))
+        }
+        _ => write_hex(f, mac),
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for b in bytes {
+        write!(f, "{:02X}", b)?;
+    }
+    Ok(())
+}
+
+impl FromStr for BACnetAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s {
+            "broadcast" => return Ok(BACnetAddress::global_broadcast()),
+            "local-broadcast" => return Ok(BACnetAddress::local_broadcast()),
+            _ => {}
+        }
+        if let Some((remote, router)) = s.split_once('@') {
+            let router = parse_mac(router)?;
+            return Ok(parse_remote(remote)?.via(BACnetAddress {
+                mac: router,
+                ..Default::default()
+            }));
+        }
+        if s.starts_with('[') || s.contains('.') || s.starts_with("0x") {
+            return Ok(BACnetAddress {
+                mac: parse_mac(s)?,
+                ..Default::default()
+            });
+        }
+        parse_remote(s)
+    }
+}
+
+// net:adr, where adr is hex (0x0E), decimal (14) or "broadcast"
+fn parse_remote(s: &str) -> Result<BACnetAddress> {
+    let invalid = |reason| Error::InvalidAddress { reason };
+    let (net, adr) = s
+        .split_once(':')
+        .ok_or_else(|| invalid("expected an IP address, a MAC address or network:MAC"))?;
+    let net = net
+        .parse::<u16>()
+        .map_err(|_| invalid("invalid network number"))?;
+    if net == 0 || net == bacnet_sys::BACNET_BROADCAST_NETWORK as u16 {
+        return Err(invalid("invalid network number"));
+    }
+    if adr == "broadcast" {
+        return Ok(BACnetAddress::remote_broadcast(net));
+    }
+    let adr = if adr.starts_with("0x") {
+        parse_hex(adr)?
+    } else {
+        vec![adr
+            .parse::<u8>()
+            .map_err(|_| invalid("invalid remote address"))?]
+    };
+    Ok(BACnetAddress::remote(net, &adr))
+}
+
+// An IPv4 address with an optional port, an IPv6 address with a port, or a hex MAC address
+fn parse_mac(s: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidAddress {
+        reason: "invalid IP or MAC address",
+    };
+    if s.starts_with("0x") {
+        return parse_hex(s);
+    }
+    if s.starts_with('[') {
+        // Unlike IPv4, the port can't be left out: it would be unclear where the address ends
+        let address = s.parse::<SocketAddrV6>().map_err(|_| invalid())?;
+        return Ok(BACnetAddress::ipv6(address).mac);
+    }
+    let address = if let Ok(address) = s.parse::<SocketAddrV4>() {
+        address
+    } else {
+        SocketAddrV4::new(s.parse().map_err(|_| invalid())?, DEFAULT_PORT)
+    };
+    Ok(BACnetAddress::ip(address).mac)
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidAddress {
+        reason: "invalid hexadecimal address",
+    };
+    let digits = s.strip_prefix("0x").ok_or_else(invalid)?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    // An odd number of digits has an implied leading 0
+    let padded = if digits.len() % 2 == 1 {
+        format!("0{}", digits)
+    } else {
+        digits.to_string()
+    };
+    (0..padded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&padded[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+// Addresses are (de)serialized in their string notation, e.g. "5:0x0E"
+#[cfg(feature = "serde")]
+impl serde::Serialize for BACnetAddress {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BACnetAddress {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> BACnetAddress {
+        s.parse()
+            .unwrap_or_else(|err| panic!("{:?} doesn't parse: {}", s, err))
+    }
+
+    #[test]
+    fn notations_round_trip() {
+        for s in &[
+            "192.168.10.96:47809",
+            "[fe80::1]:47808",
+            "0x0E",
+            "0x0102030405",
+            "5:0x0E",
+            "5:0x0E@192.168.10.1:47808",
+            "5:0x0E@0x0A",
+            "5:broadcast",
+            "broadcast",
+            "local-broadcast",
+        ] {
+            assert_eq!(parse(s).to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn notations_parse() {
+        assert_eq!(
+            parse("192.168.10.96"),
+            BACnetAddress::ip("192.168.10.96:47808".parse().unwrap())
+        );
+        assert_eq!(
+            parse("[fe80::1]:47809"),
+            BACnetAddress::ipv6("[fe80::1]:47809".parse().unwrap())
+        );
+        assert_eq!(parse("0xE").mac, vec![0x0E]);
+        assert_eq!(parse("5:0x0E"), BACnetAddress::remote(5, &[0x0E]));
+        assert_eq!(parse("5:14"), BACnetAddress::remote(5, &[0x0E]));
+        assert_eq!(parse("5:broadcast"), BACnetAddress::remote_broadcast(5));
+        assert_eq!(
+            parse("5:14@192.168.10.1"),
+            BACnetAddress::remote(5, &[0x0E])
+                .via(BACnetAddress::ip("192.168.10.1:47808".parse().unwrap()))
+        );
+        assert_eq!(parse(" broadcast "), BACnetAddress::global_broadcast());
+    }
+
+    #[test]
+    fn invalid_notations_are_rejected() {
+        for s in &[
+            "",
+            "0:5",
+            "65535:1",
+            "70000:1",
+            "5:256",
+            "5:",
+            "0x",
+            "0xG1",
+            "[fe80::1]",
+            "[fe80::1",
+            "192.168.10",
+            "192.168.10.96:70000",
+            "5:0x0E@",
+            "device",
+        ] {
+            assert!(
+                matches!(
+                    s.parse::<BACnetAddress>(),
+                    Err(Error::InvalidAddress { .. })
+                ),
+                "{:?} was accepted",
+                s
+            );
+        }
+    }
+}
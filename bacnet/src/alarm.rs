@@ -0,0 +1,423 @@
+//! Alarm and event client: GetEventInformation, GetAlarmSummary and AcknowledgeAlarm
+
+use std::ffi::CString;
+
+use chrono::{Local, NaiveDateTime, NaiveTime};
+
+use crate::timesync::{from_bacnet_date_time, to_bacnet_date_time};
+use crate::{
+    find_matching_device, send_confirmed_request, target_addresses, Ack, BACnetDevice, Error,
+    RequestStatus, Result,
+};
+
+// The stack decodes GetEventInformation-ACKs into a linked list that we have to provide. Every
+// event summary takes up about 30 bytes at the least, so allowing one per 20 bytes is enough for
+// any ACK that fits in MAX_APDU.
+const MAX_EVENTS_PER_ACK: usize = (bacnet_sys::MAX_APDU / 20) as usize;
+
+/// The event state of an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventState {
+    Normal,
+    Fault,
+    Offnormal,
+    HighLimit,
+    LowLimit,
+    /// An event state we don't know about (e.g. life-safety-alarm or a proprietary one)
+    Other(u32),
+}
+
+impl EventState {
+    pub(crate) fn from_sys(state: bacnet_sys::BACNET_EVENT_STATE) -> Self {
+        match state {
+            bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_NORMAL => EventState::Normal,
+            bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_FAULT => EventState::Fault,
+            bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_OFFNORMAL => EventState::Offnormal,
+            bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_HIGH_LIMIT => EventState::HighLimit,
+            bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_LOW_LIMIT => EventState::LowLimit,
+            other => EventState::Other(other),
+        }
+    }
+
+    fn to_sys(self) -> bacnet_sys::BACNET_EVENT_STATE {
+        match self {
+            EventState::Normal => bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_NORMAL,
+            EventState::Fault => bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_FAULT,
+            EventState::Offnormal => bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_OFFNORMAL,
+            EventState::HighLimit => bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_HIGH_LIMIT,
+            EventState::LowLimit => bacnet_sys::BACNET_EVENT_STATE_EVENT_STATE_LOW_LIMIT,
+            EventState::Other(other) => other,
+        }
+    }
+
+    // Index of the transition into this state in event timestamps and transition bit strings
+    fn transition_index(self) -> usize {
+        match self {
+            EventState::Normal => 2,
+            EventState::Fault => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// The kind of notifications an object generates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyType {
+    Alarm,
+    Event,
+    AckNotification,
+}
+
+impl NotifyType {
+    pub(crate) fn from_sys(notify_type: bacnet_sys::BACNET_NOTIFY_TYPE) -> Self {
+        match notify_type {
+            bacnet_sys::BACNET_NOTIFY_TYPE_NOTIFY_EVENT => NotifyType::Event,
+            bacnet_sys::BACNET_NOTIFY_TYPE_NOTIFY_ACK_NOTIFICATION => NotifyType::AckNotification,
+            _ => NotifyType::Alarm,
+        }
+    }
+}
+
+/// One flag per kind of event transition, as used for acked-transitions and event-enable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transitions {
+    pub to_offnormal: bool,
+    pub to_fault: bool,
+    pub to_normal: bool,
+}
+
+impl Transitions {
+    fn from_sys(bits: &bacnet_sys::BACNET_BIT_STRING) -> Self {
+        let mut bits = *bits;
+        let mut bit = |n| unsafe { bacnet_sys::bitstring_bit(&mut bits, n) };
+        Transitions {
+            to_offnormal: bit(0),
+            to_fault: bit(1),
+            to_normal: bit(2),
+        }
+    }
+}
+
+/// When an event transition happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTimestamp {
+    Time(NaiveTime),
+    SequenceNumber(u16),
+    DateTime(NaiveDateTime),
+}
+
+impl EventTimestamp {
+    // Returns `None` for unspecified timestamps, i.e. for transitions that haven't happened
+    pub(crate) fn from_sys(timestamp: &bacnet_sys::BACNET_TIMESTAMP) -> Option<Self> {
+        match u32::from(timestamp.tag) {
+            bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_TIME => {
+                let btime = unsafe { timestamp.value.time };
+                NaiveTime::from_hms_milli_opt(
+                    btime.hour.into(),
+                    btime.min.into(),
+                    btime.sec.into(),
+                    u32::from(btime.hundredths) * 10,
+                )
+                .map(EventTimestamp::Time)
+            }
+            bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_SEQUENCE => {
+                Some(EventTimestamp::SequenceNumber(unsafe {
+                    timestamp.value.sequenceNum
+                }))
+            }
+            bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_DATETIME => {
+                let datetime = unsafe { timestamp.value.dateTime };
+                from_bacnet_date_time(&datetime.date, &datetime.time).map(EventTimestamp::DateTime)
+            }
+            _ => None,
+        }
+    }
+
+    fn to_sys(self) -> bacnet_sys::BACNET_TIMESTAMP {
+        let mut timestamp = bacnet_sys::BACNET_TIMESTAMP::default();
+        match self {
+            EventTimestamp::Time(time) => {
+                let (_, btime) = to_bacnet_date_time(NaiveDateTime::new(Default::default(), time));
+                timestamp.tag = bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_TIME as u8;
+                timestamp.value.time = btime;
+            }
+            EventTimestamp::SequenceNumber(n) => {
+                timestamp.tag = bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_SEQUENCE as u8;
+                timestamp.value.sequenceNum = n;
+            }
+            EventTimestamp::DateTime(datetime) => {
+                let (date, time) = to_bacnet_date_time(datetime);
+                timestamp.tag = bacnet_sys::BACNET_TIMESTAMP_TAG_TIME_STAMP_DATETIME as u8;
+                timestamp.value.dateTime = bacnet_sys::BACNET_DATE_TIME { date, time };
+            }
+        }
+        timestamp
+    }
+}
+
+/// An object with an active event, as reported by GetEventInformation
+#[derive(Debug, Clone)]
+pub struct EventSummary {
+    pub object_type: u32,
+    pub object_instance: u32,
+    pub event_state: EventState,
+    pub acked_transitions: Transitions,
+    /// Timestamps of the last to-offnormal, to-fault and to-normal transitions (in that order)
+    pub event_timestamps: [Option<EventTimestamp>; 3],
+    pub notify_type: NotifyType,
+    pub event_enable: Transitions,
+    /// Priorities of to-offnormal, to-fault and to-normal notifications (in that order)
+    pub event_priorities: [u32; 3],
+}
+
+impl EventSummary {
+    fn from_sys(data: &bacnet_sys::BACNET_GET_EVENT_INFORMATION_DATA) -> Self {
+        EventSummary {
+            object_type: data.objectIdentifier.type_,
+            object_instance: data.objectIdentifier.instance,
+            event_state: EventState::from_sys(data.eventState),
+            acked_transitions: Transitions::from_sys(&data.acknowledgedTransitions),
+            event_timestamps: [
+                EventTimestamp::from_sys(&data.eventTimeStamps[0]),
+                EventTimestamp::from_sys(&data.eventTimeStamps[1]),
+                EventTimestamp::from_sys(&data.eventTimeStamps[2]),
+            ],
+            notify_type: NotifyType::from_sys(data.notifyType),
+            event_enable: Transitions::from_sys(&data.eventEnable),
+            event_priorities: data.eventPriorities,
+        }
+    }
+
+    /// The timestamp of the transition into the current event state
+    pub fn timestamp(&self) -> Option<EventTimestamp> {
+        self.event_timestamps[self.event_state.transition_index()]
+    }
+}
+
+/// An object in alarm, as reported by GetAlarmSummary
+#[derive(Debug, Clone)]
+pub struct AlarmSummary {
+    pub object_type: u32,
+    pub object_instance: u32,
+    pub alarm_state: EventState,
+    pub acked_transitions: Transitions,
+}
+
+/// An AcknowledgeAlarm request
+#[derive(Debug, Clone)]
+pub struct AlarmAck {
+    pub process_id: u32,
+    pub object_type: u32,
+    pub object_instance: u32,
+    /// The event state being acknowledged
+    pub event_state: EventState,
+    /// The timestamp of the transition being acknowledged
+    pub event_timestamp: EventTimestamp,
+    /// Identifies who acknowledged the alarm, e.g. an operator name
+    pub source: String,
+}
+
+impl AlarmAck {
+    /// Acknowledge the transition into the current state of `event`. Returns `None` if the device
+    /// didn't report a timestamp for that transition.
+    pub fn for_event(event: &EventSummary, source: &str) -> Option<Self> {
+        Some(AlarmAck {
+            process_id: 0,
+            object_type: event.object_type,
+            object_instance: event.object_instance,
+            event_state: event.event_state,
+            event_timestamp: event.timestamp()?,
+            source: source.to_string(),
+        })
+    }
+}
+
+impl BACnetDevice {
+    /// Get all objects with active events with GetEventInformation
+    ///
+    /// If the device has more events than fit in a single reply, the request is repeated until
+    /// the device reports that there are no more events.
+    pub fn get_event_information(&self) -> Result<Vec<EventSummary>> {
+        let mut events: Vec<EventSummary> = Vec::new();
+        loop {
+            let mut last_received = events.last().map(|event| bacnet_sys::BACNET_OBJECT_ID {
+                type_: event.object_type,
+                instance: event.object_instance,
+            });
+            let last_received_ptr = last_received
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |id| id as *mut _);
+
+            // Send_GetEvent() doesn't set up a confirmed transaction, so we do it ourselves
+            let ack = self.confirmed_request(|| {
+                send_confirmed_request(self.device_id, |apdu, invoke_id| unsafe {
+                    bacnet_sys::getevent_encode_apdu(
+                        apdu.as_mut_ptr(),
+                        invoke_id,
+                        last_received_ptr,
+                    )
+                })
+            })?;
+            match ack {
+                Some(Ack::EventInformation {
+                    events: more,
+                    more_events,
+                }) => {
+                    debug!(
+                        "got {} events (more events: {}) from device {}",
+                        more.len(),
+                        more_events,
+                        self.device_id
+                    );
+                    let done = !more_events || more.is_empty();
+                    events.extend(more);
+                    if done {
+                        break;
+                    }
+                }
+                _ => return Err(Error::NoValueWasExtracted),
+            }
+        }
+        Ok(events)
+    }
+
+    /// Get all objects in alarm with GetAlarmSummary
+    pub fn get_alarm_summary(&self) -> Result<Vec<AlarmSummary>> {
+        match self
+            .confirmed_request(|| unsafe { bacnet_sys::Send_Get_Alarm_Summary(self.device_id) })?
+        {
+            Some(Ack::AlarmSummary(alarms)) => Ok(alarms),
+            _ => Err(Error::NoValueWasExtracted),
+        }
+    }
+
+    /// Acknowledge an alarm with AcknowledgeAlarm. The acknowledgement is timestamped with the
+    /// current local time.
+    pub fn acknowledge_alarm(&self, ack: &AlarmAck) -> Result<()> {
+        let source = CString::new(ack.source.as_str()).map_err(|_| Error::InvalidAckSource)?;
+        let mut data = bacnet_sys::BACNET_ALARM_ACK_DATA {
+            ackProcessIdentifier: ack.process_id,
+            eventObjectIdentifier: bacnet_sys::BACNET_OBJECT_ID {
+                type_: ack.object_type,
+                instance: ack.object_instance,
+            },
+            eventStateAcked: ack.event_state.to_sys(),
+            eventTimeStamp: ack.event_timestamp.to_sys(),
+            ackTimeStamp: EventTimestamp::DateTime(Local::now().naive_local()).to_sys(),
+            ..Default::default()
+        };
+        if !unsafe { bacnet_sys::characterstring_init_ansi(&mut data.ackSource, source.as_ptr()) } {
+            return Err(Error::InvalidAckSource);
+        }
+
+        self.confirmed_request(|| unsafe {
+            bacnet_sys::Send_Alarm_Acknowledgement(self.device_id, &mut data)
+        })
+        .map(|_| ())
+    }
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn get_event_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("get_event_ack_handler", || {
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            // Chain up the entries for the decoder to fill in
+            let mut data =
+                vec![bacnet_sys::BACNET_GET_EVENT_INFORMATION_DATA::default(); MAX_EVENTS_PER_ACK];
+            for i in 1..data.len() {
+                let next: *mut _ = &mut data[i];
+                data[i - 1].next = next;
+            }
+            let mut more_events = false;
+
+            let request =
+                unsafe { std::slice::from_raw_parts(service_request, service_len.into()) };
+            let len = if let [0x0e, 0x0f, 0x19, more, ..] = request {
+                // The decoder can't handle an empty list of events
+                more_events = *more != 0;
+                0
+            } else if request.starts_with(&[0x0e, 0x0f]) {
+                -1
+            } else {
+                unsafe {
+                    bacnet_sys::getevent_ack_decode_service_request(
+                        service_request,
+                        service_len.into(),
+                        data.as_mut_ptr(),
+                        &mut more_events,
+                    )
+                }
+            };
+            if len == 0 {
+                target.ack = Some(Ok(Ack::EventInformation {
+                    events: Vec::new(),
+                    more_events,
+                }));
+            } else if len > 0 {
+                // The decoder terminates the list after the last event
+                let count = data
+                    .iter()
+                    .position(|d| d.next.is_null())
+                    .map_or(0, |i| i + 1);
+                let events = data[..count].iter().map(EventSummary::from_sys).collect();
+                target.ack = Some(Ok(Ack::EventInformation {
+                    events,
+                    more_events,
+                }));
+            } else {
+                error!("<decode failed>");
+                target.ack = Some(Err(Error::FailedToDecodeData));
+            }
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn get_alarm_summary_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("get_alarm_summary_ack_handler", || {
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let mut alarms = Vec::new();
+            let mut offset = 0;
+            let mut result = Ok(());
+            while offset < usize::from(service_len) {
+                let mut data = bacnet_sys::BACNET_GET_ALARM_SUMMARY_DATA::default();
+                let len = unsafe {
+                    bacnet_sys::get_alarm_summary_ack_decode_apdu_data(
+                        service_request.add(offset),
+                        usize::from(service_len) - offset,
+                        &mut data,
+                    )
+                };
+                if len <= 0 {
+                    error!("<decode failed>");
+                    result = Err(Error::FailedToDecodeData);
+                    break;
+                }
+                offset += len as usize;
+                alarms.push(AlarmSummary {
+                    object_type: data.objectIdentifier.type_,
+                    object_instance: data.objectIdentifier.instance,
+                    alarm_state: EventState::from_sys(data.alarmState),
+                    acked_transitions: Transitions::from_sys(&data.acknowledgedTransitions),
+                });
+            }
+            target.ack = Some(result.map(|_| Ack::AlarmSummary(alarms)));
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
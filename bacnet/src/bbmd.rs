@@ -0,0 +1,293 @@
+//! Broadcast Distribution Table and Foreign Device Table management of BBMDs
+//!
+//! These are BVLL messages rather than BACnet services, which the stack handles (and for the most
+//! part discards) inside `bip_receive()`. We therefore talk to the BBMD over a socket of our own,
+//! using the stack's BVLC encoders and decoders, like the `readbdt` and `readfdt` apps do.
+
+use crate::{Error, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// An entry of a Broadcast Distribution Table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BdtEntry {
+    /// The B/IP address of the BBMD
+    pub address: SocketAddrV4,
+    /// The broadcast distribution mask. 255.255.255.255 means broadcasts are forwarded to the BBMD
+    /// itself, otherwise they're sent as directed broadcasts on its subnet.
+    pub mask: Ipv4Addr,
+}
+
+impl BdtEntry {
+    pub fn new(address: SocketAddrV4, mask: Ipv4Addr) -> Self {
+        BdtEntry { address, mask }
+    }
+
+    fn from_sys(entry: &bacnet_sys::BACNET_IP_BROADCAST_DISTRIBUTION_TABLE_ENTRY) -> Self {
+        BdtEntry {
+            address: from_ip_address(&entry.dest_address),
+            mask: Ipv4Addr::from(entry.broadcast_mask.address),
+        }
+    }
+
+    fn to_sys(self) -> bacnet_sys::BACNET_IP_BROADCAST_DISTRIBUTION_TABLE_ENTRY {
+        bacnet_sys::BACNET_IP_BROADCAST_DISTRIBUTION_TABLE_ENTRY {
+            valid: true,
+            dest_address: to_ip_address(&self.address),
+            broadcast_mask: bacnet_sys::BACNET_IP_BROADCAST_DISTRIBUTION_MASK {
+                address: self.mask.octets(),
+            },
+            next: std::ptr::null_mut(),
+        }
+    }
+}
+
+/// An entry of a Foreign Device Table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdtEntry {
+    /// The B/IP address of the registered foreign device
+    pub address: SocketAddrV4,
+    /// The time-to-live the foreign device registered with (in seconds)
+    pub ttl: u16,
+    /// The number of seconds until the registration expires, including the 30 second grace period
+    pub remaining: u16,
+}
+
+impl FdtEntry {
+    fn from_sys(entry: &bacnet_sys::BACNET_IP_FOREIGN_DEVICE_TABLE_ENTRY) -> Self {
+        FdtEntry {
+            address: from_ip_address(&entry.dest_address),
+            ttl: entry.ttl_seconds,
+            remaining: entry.ttl_seconds_remaining,
+        }
+    }
+}
+
+/// A BBMD to read or administer the tables of.
+// Bbmd::new(addr).timeout(Duration::from_secs(1)).read_bdt()
+#[derive(Debug, Clone)]
+pub struct Bbmd {
+    address: SocketAddrV4,
+
+    /// How long to wait for the BBMD to answer
+    timeout: Duration,
+}
+
+impl Bbmd {
+    pub fn new(address: SocketAddrV4) -> Self {
+        Bbmd {
+            address,
+            timeout: Duration::from_secs(3),
+        }
+    }
+
+    /// Set the amount of time to wait for an answer from the BBMD. Default: 3s
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Read the Broadcast Distribution Table of the BBMD (Read-Broadcast-Distribution-Table).
+    pub fn read_bdt(&self) -> Result<Vec<BdtEntry>> {
+        let mut request = [0u8; 4];
+        let len = unsafe {
+            bacnet_sys::bvlc_encode_read_broadcast_distribution_table(
+                request.as_mut_ptr(),
+                request.len() as u16,
+            )
+        };
+        let mut payload = self.request(
+            &request[..len as usize],
+            bacnet_sys::BVLC_READ_BROADCAST_DIST_TABLE_ACK,
+        )?;
+
+        let count = payload.len() / bacnet_sys::BACNET_IP_BDT_ENTRY_SIZE as usize;
+        let mut entries =
+            vec![bacnet_sys::BACNET_IP_BROADCAST_DISTRIBUTION_TABLE_ENTRY::default(); count];
+        if count > 0 {
+            let len = unsafe {
+                bacnet_sys::bvlc_broadcast_distribution_table_link_array(
+                    entries.as_mut_ptr(),
+                    count,
+                );
+                bacnet_sys::bvlc_decode_read_broadcast_distribution_table_ack(
+                    payload.as_mut_ptr(),
+                    payload.len() as u16,
+                    entries.as_mut_ptr(),
+                )
+            };
+            if len == 0 {
+                return Err(Error::DecodingError);
+            }
+        }
+        Ok(entries
+            .iter()
+            .filter(|entry| entry.valid)
+            .map(BdtEntry::from_sys)
+            .collect())
+    }
+
+    /// Replace the Broadcast Distribution Table of the BBMD (Write-Broadcast-Distribution-Table).
+    pub fn write_bdt(&self, entries: &[BdtEntry]) -> Result<()> {
+        let mut list = entries
+            .iter()
+            .map(|entry| entry.to_sys())
+            .collect::<Vec<_>>();
+        let mut request = [0u8; bacnet_sys::MAX_MPDU as usize];
+        let len = unsafe {
+            if !list.is_empty() {
+                bacnet_sys::bvlc_broadcast_distribution_table_link_array(
+                    list.as_mut_ptr(),
+                    list.len(),
+                );
+                bacnet_sys::bvlc_encode_write_broadcast_distribution_table(
+                    request.as_mut_ptr(),
+                    request.len() as u16,
+                    list.as_mut_ptr(),
+                )
+            } else {
+                bacnet_sys::bvlc_encode_header(
+                    request.as_mut_ptr(),
+                    request.len() as u16,
+                    bacnet_sys::BVLC_WRITE_BROADCAST_DISTRIBUTION_TABLE as u8,
+                    4,
+                )
+            }
+        };
+        if len <= 0 {
+            return Err(Error::FailedToEncodeValue);
+        }
+        self.request(&request[..len as usize], bacnet_sys::BVLC_RESULT)
+            .map(|_| ())
+    }
+
+    /// Read the Foreign Device Table of the BBMD (Read-Foreign-Device-Table).
+    pub fn read_fdt(&self) -> Result<Vec<FdtEntry>> {
+        let mut request = [0u8; 4];
+        let len = unsafe {
+            bacnet_sys::bvlc_encode_read_foreign_device_table(
+                request.as_mut_ptr(),
+                request.len() as u16,
+            )
+        };
+        let mut payload = self.request(
+            &request[..len as usize],
+            bacnet_sys::BVLC_READ_FOREIGN_DEVICE_TABLE_ACK,
+        )?;
+
+        let count = payload.len() / bacnet_sys::BACNET_IP_FDT_ENTRY_SIZE as usize;
+        let mut entries = vec![bacnet_sys::BACNET_IP_FOREIGN_DEVICE_TABLE_ENTRY::default(); count];
+        if count > 0 {
+            let len = unsafe {
+                bacnet_sys::bvlc_foreign_device_table_link_array(entries.as_mut_ptr(), count);
+                bacnet_sys::bvlc_decode_read_foreign_device_table_ack(
+                    payload.as_mut_ptr(),
+                    payload.len() as u16,
+                    entries.as_mut_ptr(),
+                )
+            };
+            if len == 0 {
+                return Err(Error::DecodingError);
+            }
+        }
+        Ok(entries
+            .iter()
+            .filter(|entry| entry.valid)
+            .map(FdtEntry::from_sys)
+            .collect())
+    }
+
+    /// Remove a foreign device from the Foreign Device Table of the BBMD
+    /// (Delete-Foreign-Device-Table-Entry).
+    pub fn delete_fdt_entry(&self, address: SocketAddrV4) -> Result<()> {
+        let mut ip_address = to_ip_address(&address);
+        let mut request = [0u8; 10];
+        let len = unsafe {
+            bacnet_sys::bvlc_encode_delete_foreign_device(
+                request.as_mut_ptr(),
+                request.len() as u16,
+                &mut ip_address,
+            )
+        };
+        self.request(&request[..len as usize], bacnet_sys::BVLC_RESULT)
+            .map(|_| ())
+    }
+
+    // Send a BVLL request to the BBMD, and wait for the answer of the given BVLC function. Returns
+    // the payload following the BVLC header. A BVLC-Result NAK is returned as an error.
+    fn request(&self, request: &[u8], function: u32) -> Result<Vec<u8>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(Error::Io)?;
+        socket.send_to(request, self.address).map_err(Error::Io)?;
+
+        let mut buf = [0u8; bacnet_sys::MAX_MPDU as usize];
+        let start = Instant::now();
+        loop {
+            let remaining = self
+                .timeout
+                .checked_sub(start.elapsed())
+                .unwrap_or_default();
+            if remaining == Duration::default() {
+                break;
+            }
+            socket
+                .set_read_timeout(Some(remaining))
+                .map_err(Error::Io)?;
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    break
+                }
+                Err(e) => return Err(Error::Io(e)),
+            };
+            if from.ip() != IpAddr::V4(*self.address.ip()) {
+                continue;
+            }
+
+            let mut message_type = 0;
+            let mut message_length = 0;
+            let header_len = unsafe {
+                bacnet_sys::bvlc_decode_header(
+                    buf.as_mut_ptr(),
+                    len as u16,
+                    &mut message_type,
+                    &mut message_length,
+                )
+            };
+            if header_len != 4 || usize::from(message_length) > len || message_length < 4 {
+                continue;
+            }
+            let payload = &mut buf[4..usize::from(message_length)];
+            if u32::from(message_type) == bacnet_sys::BVLC_RESULT {
+                let mut result_code = 0;
+                unsafe {
+                    bacnet_sys::bvlc_decode_result(
+                        payload.as_mut_ptr(),
+                        payload.len() as u16,
+                        &mut result_code,
+                    )
+                };
+                if u32::from(result_code) != bacnet_sys::BVLC_RESULT_SUCCESSFUL_COMPLETION {
+                    return Err(Error::BvlcNak { result_code });
+                }
+            }
+            if u32::from(message_type) == function {
+                return Ok(payload.to_vec());
+            }
+        }
+        Err(Error::BbmdTimeout)
+    }
+}
+
+fn from_ip_address(address: &bacnet_sys::BACNET_IP_ADDRESS) -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::from(address.address), address.port)
+}
+
+fn to_ip_address(address: &SocketAddrV4) -> bacnet_sys::BACNET_IP_ADDRESS {
+    bacnet_sys::BACNET_IP_ADDRESS {
+        address: address.ip().octets(),
+        port: address.port(),
+    }
+}
@@ -0,0 +1,298 @@
+//! What a device supports, from the protocol-services-supported, protocol-object-types-supported
+//! and segmentation-supported properties of its device object
+//!
+//! Once known, the capabilities of a connected device decide whether objects are read with
+//! ReadPropertyMultiple or ReadProperty, and whether arrays are read whole or an element at a
+//! time. Other services can be checked with `Capabilities::supports()`.
+//!
+//! Choosing between SubscribeCOV and polling isn't done here: the client has no COV
+//! subscriptions yet. `Capabilities::supports_cov()` tells whether the device would accept them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::epics::{object_type_name, service_name, EpicsObject, ObjectId};
+use crate::value::BACnetValue;
+use crate::{target_addresses, BACnetDevice, Epics, ObjectType, Result, Segmentation};
+
+// Define `Service` with the bits of protocol-services-supported
+macro_rules! services {
+    ($($variant:ident = $value:ident,)*) => {
+        /// A service in protocol-services-supported. Its bit is the `BACNET_SERVICES_SUPPORTED`
+        /// value of bacenum.h.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Service {
+            $($variant,)*
+        }
+
+        impl Service {
+            /// All services, in the order of their bits
+            pub const ALL: &'static [Service] = &[$(Service::$variant,)*];
+
+            pub fn from_bit(bit: u32) -> Option<Self> {
+                match bit {
+                    $(bacnet_sys::$value => Some(Service::$variant),)*
+                    _ => None,
+                }
+            }
+
+            pub fn bit(self) -> u32 {
+                match self {
+                    $(Service::$variant => bacnet_sys::$value,)*
+                }
+            }
+        }
+    };
+}
+
+services! {
+    AcknowledgeAlarm = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_ACKNOWLEDGE_ALARM,
+    ConfirmedCovNotification = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_CONFIRMED_COV_NOTIFICATION,
+    ConfirmedEventNotification = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_CONFIRMED_EVENT_NOTIFICATION,
+    GetAlarmSummary = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_ALARM_SUMMARY,
+    GetEnrollmentSummary = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_ENROLLMENT_SUMMARY,
+    SubscribeCov = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_SUBSCRIBE_COV,
+    AtomicReadFile = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_ATOMIC_READ_FILE,
+    AtomicWriteFile = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_ATOMIC_WRITE_FILE,
+    AddListElement = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_ADD_LIST_ELEMENT,
+    RemoveListElement = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_REMOVE_LIST_ELEMENT,
+    CreateObject = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_CREATE_OBJECT,
+    DeleteObject = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_DELETE_OBJECT,
+    ReadProperty = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_PROPERTY,
+    ReadPropertyConditional = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_PROP_CONDITIONAL,
+    ReadPropertyMultiple = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_PROP_MULTIPLE,
+    WriteProperty = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WRITE_PROPERTY,
+    WritePropertyMultiple = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WRITE_PROP_MULTIPLE,
+    DeviceCommunicationControl = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_DEVICE_COMMUNICATION_CONTROL,
+    PrivateTransfer = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_PRIVATE_TRANSFER,
+    TextMessage = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_TEXT_MESSAGE,
+    ReinitializeDevice = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_REINITIALIZE_DEVICE,
+    VtOpen = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_VT_OPEN,
+    VtClose = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_VT_CLOSE,
+    VtData = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_VT_DATA,
+    Authenticate = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_AUTHENTICATE,
+    RequestKey = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_REQUEST_KEY,
+    IAm = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_I_AM,
+    IHave = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_I_HAVE,
+    UnconfirmedCovNotification = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UNCONFIRMED_COV_NOTIFICATION,
+    UnconfirmedEventNotification = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UNCONFIRMED_EVENT_NOTIFICATION,
+    UnconfirmedPrivateTransfer = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UNCONFIRMED_PRIVATE_TRANSFER,
+    UnconfirmedTextMessage = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UNCONFIRMED_TEXT_MESSAGE,
+    TimeSynchronization = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_TIME_SYNCHRONIZATION,
+    WhoHas = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WHO_HAS,
+    WhoIs = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WHO_IS,
+    ReadRange = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_RANGE,
+    UtcTimeSynchronization = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UTC_TIME_SYNCHRONIZATION,
+    LifeSafetyOperation = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_LIFE_SAFETY_OPERATION,
+    SubscribeCovProperty = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_SUBSCRIBE_COV_PROPERTY,
+    GetEventInformation = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_EVENT_INFORMATION,
+    WriteGroup = BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WRITE_GROUP,
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&service_name(self.bit()))
+    }
+}
+
+/// The services in protocol-services-supported
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServicesSupported {
+    bits: Vec<bool>,
+}
+
+impl ServicesSupported {
+    pub fn from_bits(bits: &[bool]) -> Self {
+        ServicesSupported {
+            bits: bits.to_vec(),
+        }
+    }
+
+    /// The services of a bit string value, `None` for any other value
+    pub fn from_value(value: &BACnetValue) -> Option<Self> {
+        match value {
+            BACnetValue::BitString(bits) => Some(Self::from_bits(bits)),
+            _ => None,
+        }
+    }
+
+    /// Whether no service is supported, e.g. because protocol-services-supported wasn't read
+    pub fn is_empty(&self) -> bool {
+        !self.bits.contains(&true)
+    }
+
+    pub fn contains(&self, service: Service) -> bool {
+        self.bits.get(service.bit() as usize) == Some(&true)
+    }
+
+    /// The supported services. Bits of services we don't know of are left out.
+    pub fn iter(&self) -> impl Iterator<Item = Service> + '_ {
+        supported(&self.bits).filter_map(Service::from_bit)
+    }
+}
+
+impl fmt::Display for ServicesSupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_list(f, self.iter())
+    }
+}
+
+/// The object types in protocol-object-types-supported
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectTypesSupported {
+    bits: Vec<bool>,
+}
+
+impl ObjectTypesSupported {
+    pub fn from_bits(bits: &[bool]) -> Self {
+        ObjectTypesSupported {
+            bits: bits.to_vec(),
+        }
+    }
+
+    /// The object types of a bit string value, `None` for any other value
+    pub fn from_value(value: &BACnetValue) -> Option<Self> {
+        match value {
+            BACnetValue::BitString(bits) => Some(Self::from_bits(bits)),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, object_type: ObjectType) -> bool {
+        self.bits.get(object_type as usize) == Some(&true)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ObjectType> + '_ {
+        supported(&self.bits)
+    }
+}
+
+impl fmt::Display for ObjectTypesSupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_list(f, self.iter().map(object_type_name))
+    }
+}
+
+// The numbers of the bits that are set
+fn supported(bits: &[bool]) -> impl Iterator<Item = u32> + '_ {
+    bits.iter()
+        .enumerate()
+        .filter(|(_, bit)| **bit)
+        .map(|(i, _)| i as u32)
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    items: impl Iterator<Item = T>,
+) -> fmt::Result {
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// What a device supports, according to its device object
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub services: ServicesSupported,
+    pub object_types: ObjectTypesSupported,
+    /// From segmentation-supported, if the device object has it
+    pub segmentation: Option<Segmentation>,
+}
+
+impl Capabilities {
+    /// The capabilities in the properties of a device object
+    pub fn from_device(device: &EpicsObject) -> Self {
+        let property = |id| device.property(id);
+        Capabilities {
+            services: property(bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_SERVICES_SUPPORTED)
+                .and_then(ServicesSupported::from_value)
+                .unwrap_or_default(),
+            object_types: property(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_OBJECT_TYPES_SUPPORTED,
+            )
+            .and_then(ObjectTypesSupported::from_value)
+            .unwrap_or_default(),
+            segmentation: match property(bacnet_sys::BACNET_PROPERTY_ID_PROP_SEGMENTATION_SUPPORTED)
+            {
+                Some(BACnetValue::Enum(segmentation, _)) => Segmentation::from_sys(*segmentation),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn supports(&self, service: Service) -> bool {
+        self.services.contains(service)
+    }
+
+    /// Whether objects can be read with ReadPropertyMultiple
+    pub fn supports_rpm(&self) -> bool {
+        self.supports(Service::ReadPropertyMultiple)
+    }
+
+    /// Whether the device can be subscribed to with SubscribeCOV, instead of polling values
+    pub fn supports_cov(&self) -> bool {
+        self.supports(Service::SubscribeCov)
+    }
+
+    /// Whether the device sends answers that don't fit in an APDU in segments
+    pub fn segments_answers(&self) -> bool {
+        matches!(
+            self.segmentation,
+            Some(Segmentation::Both) | Some(Segmentation::Transmit)
+        )
+    }
+}
+
+impl Epics {
+    /// The capabilities of the device, `None` if the `Epics` has no device object
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.device().map(Capabilities::from_device)
+    }
+}
+
+impl BACnetDevice {
+    /// Read the capabilities of the device from its device object, and use them from now on.
+    ///
+    /// `epics()` and `interrogate()` do this by themselves when they've read the device object.
+    pub fn read_capabilities(&self) -> Result<Capabilities> {
+        let mut properties = HashMap::new();
+        for property in &[
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_SERVICES_SUPPORTED,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_OBJECT_TYPES_SUPPORTED,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_SEGMENTATION_SUPPORTED,
+        ] {
+            let value = self.read_prop(
+                bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
+                self.device_id,
+                *property,
+            )?;
+            properties.insert(*property, value);
+        }
+        let id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE, self.device_id);
+        let capabilities = Capabilities::from_device(&EpicsObject::new(id, properties));
+        self.set_capabilities(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// The capabilities of the device, if they've been read
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        target_addresses()
+            .get(&self.device_id)
+            .and_then(|target| target.capabilities.clone())
+    }
+
+    // Remember the capabilities. Unless we've found out otherwise already, they decide whether
+    // ReadPropertyMultiple is used.
+    pub(crate) fn set_capabilities(&self, capabilities: Capabilities) {
+        if let Some(target) = target_addresses().get_mut(&self.device_id) {
+            if target.rpm_supported.is_none() && !capabilities.services.is_empty() {
+                target.rpm_supported = Some(capabilities.supports_rpm());
+            }
+            target.capabilities = Some(capabilities);
+        }
+    }
+}
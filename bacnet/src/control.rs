@@ -0,0 +1,147 @@
+//! DeviceCommunicationControl and ReinitializeDevice
+
+use std::ffi::CString;
+use std::time::Duration;
+
+use crate::{BACnetDevice, BACnetErr, Error, ErrorClass, ErrorCode, Result};
+
+/// The state requested with DeviceCommunicationControl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommunicationState {
+    Enable,
+    Disable,
+    /// Stop initiating communication, but keep responding to requests
+    DisableInitiation,
+}
+
+impl CommunicationState {
+    fn to_sys(self) -> bacnet_sys::BACNET_COMMUNICATION_ENABLE_DISABLE {
+        match self {
+            CommunicationState::Enable => {
+                bacnet_sys::BACNET_COMMUNICATION_ENABLE_DISABLE_COMMUNICATION_ENABLE
+            }
+            CommunicationState::Disable => {
+                bacnet_sys::BACNET_COMMUNICATION_ENABLE_DISABLE_COMMUNICATION_DISABLE
+            }
+            CommunicationState::DisableInitiation => {
+                bacnet_sys::BACNET_COMMUNICATION_ENABLE_DISABLE_COMMUNICATION_DISABLE_INITIATION
+            }
+        }
+    }
+}
+
+/// The state requested with ReinitializeDevice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReinitializeState {
+    ColdStart,
+    WarmStart,
+    StartBackup,
+    EndBackup,
+    StartRestore,
+    EndRestore,
+    AbortRestore,
+    ActivateChanges,
+}
+
+impl ReinitializeState {
+    fn to_sys(self) -> bacnet_sys::BACNET_REINITIALIZED_STATE {
+        use ReinitializeState::*;
+        match self {
+            ColdStart => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_COLDSTART,
+            WarmStart => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_WARMSTART,
+            StartBackup => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_STARTBACKUP,
+            EndBackup => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_ENDBACKUP,
+            StartRestore => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_STARTRESTORE,
+            EndRestore => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_ENDRESTORE,
+            AbortRestore => bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_ABORTRESTORE,
+            ActivateChanges => {
+                bacnet_sys::BACNET_REINITIALIZED_STATE_BACNET_REINIT_ACTIVATE_CHANGES
+            }
+        }
+    }
+}
+
+impl BACnetDevice {
+    /// Enable or disable communication of the device with DeviceCommunicationControl
+    ///
+    /// With a `duration` the device returns to normal operation by itself once it has elapsed
+    /// (the duration is sent in whole minutes, rounded up). Without it the state holds until it
+    /// is changed again.
+    pub fn device_communication_control(
+        &self,
+        state: CommunicationState,
+        duration: Option<Duration>,
+        password: Option<&str>,
+    ) -> Result<()> {
+        // A duration of 0 means "no duration", i.e. indefinitely
+        let minutes = duration.map_or(0, |d| {
+            let minutes = d.as_secs().div_ceil(60);
+            minutes.clamp(1, u16::MAX.into()) as u16
+        });
+        let password = password.map(to_password).transpose()?;
+        let password_ptr = password
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |p| p.as_ptr() as *mut _);
+
+        self.confirmed_request(|| unsafe {
+            bacnet_sys::Send_Device_Communication_Control_Request(
+                self.device_id,
+                minutes,
+                state.to_sys(),
+                password_ptr,
+            )
+        })
+        .map(|_| ())
+        .map_err(device_control_error)
+    }
+
+    /// Restart the device, or prepare it for backup and restore, with ReinitializeDevice
+    pub fn reinitialize_device(
+        &self,
+        state: ReinitializeState,
+        password: Option<&str>,
+    ) -> Result<()> {
+        let password = password.map(to_password).transpose()?;
+        let password_ptr = password
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |p| p.as_ptr() as *mut _);
+
+        self.confirmed_request(|| unsafe {
+            bacnet_sys::Send_Reinitialize_Device_Request(
+                self.device_id,
+                state.to_sys(),
+                password_ptr,
+            )
+        })
+        .map(|_| ())
+        .map_err(device_control_error)
+    }
+}
+
+fn to_password(password: &str) -> Result<CString> {
+    if password.chars().count() > 20 {
+        return Err(Error::InvalidPassword);
+    }
+    CString::new(password).map_err(|_| Error::InvalidPassword)
+}
+
+// Both services report a wrong password and a refusal with the same error class/code pairs
+fn device_control_error(err: Error) -> Error {
+    match err {
+        Error::BacnetError {
+            error:
+                BACnetErr::Error {
+                    class: ErrorClass::Security,
+                    code: ErrorCode::PasswordFailure,
+                },
+        } => Error::PasswordFailure,
+        Error::BacnetError {
+            error:
+                BACnetErr::Error {
+                    code: ErrorCode::ServiceRequestDenied,
+                    ..
+                },
+        } => Error::ServiceRequestDenied,
+        err => err,
+    }
+}
@@ -1,8 +1,844 @@
+//! EPICS (Electronic Protocol Implementation Conformance Statement) of a device
+//!
+//! `Epics::text()` writes it in the format of the stack's `bacepics` tool (apps/epics), which is
+//! what VTS and BTL testers expect. Such documents are parsed back with `str::parse()`.
+
 use crate::value::BACnetValue;
-use std::collections::HashMap;
+use crate::{ObjectPropertyId, ObjectType};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+mod diff;
+mod parse;
+
+pub use diff::{EpicsDiff, ObjectDiff, PropertyChange};
+
+/// The identifier of an object: its type and instance number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectId {
+    pub object_type: ObjectType,
+    pub object_instance: u32,
+}
+
+impl ObjectId {
+    pub fn new(object_type: ObjectType, object_instance: u32) -> Self {
+        ObjectId {
+            object_type,
+            object_instance,
+        }
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({}, {})",
+            object_type_name(self.object_type),
+            self.object_instance
+        )
+    }
+}
 
-#[derive(Debug, Default)]
+/// All objects of a device and their properties, as read by `BACnetDevice::epics()` or parsed
+/// from an EPICS document.
+// epics.object(ObjectId::new(OBJECT_ANALOG_VALUE, 22))?.property(PROP_UNITS)
+// let spec: Epics = std::fs::read_to_string("vendor-epics.tpi")?.parse()?;
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Epics {
-    pub device: HashMap<String, BACnetValue>,
-    pub object_list: Vec<HashMap<String, BACnetValue>>,
+    /// The identifier of the device object
+    pub device_id: ObjectId,
+    /// All objects, including the device object
+    #[cfg_attr(feature = "serde", serde(with = "values"))]
+    pub objects: BTreeMap<ObjectId, EpicsObject>,
+}
+
+impl Epics {
+    /// The device object
+    pub fn device(&self) -> Option<&EpicsObject> {
+        self.objects.get(&self.device_id)
+    }
+
+    pub fn object(&self, id: ObjectId) -> Option<&EpicsObject> {
+        self.objects.get(&id)
+    }
+
+    /// The EPICS as a text document.
+    // println!("{}", epics.text().show_values(true))
+    pub fn text(&self) -> EpicsText<'_> {
+        EpicsText {
+            epics: self,
+            show_values: false,
+        }
+    }
+}
+
+impl fmt::Display for Epics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+/// An object of an `Epics`, with the properties that could be read.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpicsObject {
+    pub id: ObjectId,
+    #[cfg_attr(feature = "serde", serde(with = "values"))]
+    pub properties: BTreeMap<ObjectPropertyId, EpicsProperty>,
+}
+
+impl EpicsObject {
+    pub(crate) fn new(id: ObjectId, properties: HashMap<ObjectPropertyId, BACnetValue>) -> Self {
+        EpicsObject {
+            id,
+            properties: properties
+                .into_iter()
+                .map(|(id, value)| (id, EpicsProperty::new(id, value)))
+                .collect(),
+        }
+    }
+
+    /// The value of the property with the given ID
+    pub fn property(&self, id: ObjectPropertyId) -> Option<&BACnetValue> {
+        self.properties.get(&id).map(|property| &property.value)
+    }
+
+    /// The value of the property with the given name, e.g. "present-value" or "proprietary-512"
+    pub fn property_by_name(&self, name: &str) -> Option<&BACnetValue> {
+        self.properties
+            .values()
+            .find(|property| property.name == name)
+            .map(|property| &property.value)
+    }
+}
+
+/// A property of an `EpicsObject`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpicsProperty {
+    pub id: ObjectPropertyId,
+    /// The name of the property, "proprietary-<id>" for proprietary properties
+    pub name: String,
+    pub value: BACnetValue,
+}
+
+impl EpicsProperty {
+    pub fn new(id: ObjectPropertyId, value: BACnetValue) -> Self {
+        EpicsProperty {
+            id,
+            name: property_name(id),
+            value,
+        }
+    }
+
+    /// Proprietary properties have IDs of 512 and up
+    pub fn is_proprietary(&self) -> bool {
+        self.id >= PROPRIETARY_MIN
+    }
+}
+
+const PROPRIETARY_MIN: ObjectPropertyId = 512;
+
+fn property_name(id: ObjectPropertyId) -> String {
+    if id >= PROPRIETARY_MIN {
+        format!("proprietary-{}", id)
+    } else {
+        crate::cstr(unsafe { bacnet_sys::bactext_property_name(id) })
+    }
+}
+
+/// An `Epics` written as a text document, as produced by `Epics::text()`.
+pub struct EpicsText<'a> {
+    epics: &'a Epics,
+
+    /// Show the values of volatile properties, like present-value, instead of `?`
+    show_values: bool,
+}
+
+impl<'a> EpicsText<'a> {
+    /// Show the values of volatile properties (present-value, local-time, etc.) instead of `?`,
+    /// like `bacepics -v`. Default: false
+    pub fn show_values(mut self, show_values: bool) -> Self {
+        self.show_values = show_values;
+        self
+    }
+
+    fn write_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = self.epics.device();
+        let property = |id| device.and_then(|device| device.property(id));
+        let string = |id, default: &'static str| match property(id) {
+            Some(BACnetValue::String(s)) => s.clone(),
+            _ => default.to_string(),
+        };
+
+        writeln!(f, "PICS 0")?;
+        writeln!(f, "BACnet Protocol Implementation Conformance Statement\n")?;
+        writeln!(f, "--\n--")?;
+        writeln!(f, "-- Generated by the bacnet crate")?;
+        writeln!(f, "-- \n--\n")?;
+        writeln!(
+            f,
+            "Vendor Name: \"{}\"",
+            string(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_VENDOR_NAME,
+                "your vendor name here"
+            )
+        )?;
+        writeln!(
+            f,
+            "Product Name: \"{}\"",
+            string(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_MODEL_NAME,
+                "your product name here"
+            )
+        )?;
+        writeln!(
+            f,
+            "Product Model Number: \"{}\"",
+            string(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_MODEL_NAME,
+                "your model number here"
+            )
+        )?;
+        writeln!(
+            f,
+            "Product Description: \"{}\"\n",
+            string(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_DESCRIPTION,
+                "your product description here"
+            )
+        )?;
+
+        let services =
+            match property(bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_SERVICES_SUPPORTED) {
+                Some(BACnetValue::BitString(bits)) => bits.as_slice(),
+                _ => &[],
+            };
+        let supported = |service: u32| services.get(service as usize) == Some(&true);
+
+        writeln!(f, "BIBBs Supported:")?;
+        writeln!(f, "{{")?;
+        if services.is_empty() {
+            writeln!(f, " DS-RP-B")?;
+        } else {
+            writeln!(f, "-- derived from the services reported by this device")?;
+            for (service, bibb) in BIBBS {
+                if supported(*service) {
+                    writeln!(f, " {}", bibb)?;
+                }
+            }
+        }
+        writeln!(f, "}}\n")?;
+
+        writeln!(f, "BACnet Standard Application Services Supported:")?;
+        writeln!(f, "{{")?;
+        if services.is_empty() {
+            writeln!(f, " ReadProperty                   Execute")?;
+        } else {
+            writeln!(f, "-- services reported by this device")?;
+            for (i, _) in services.iter().enumerate().filter(|(_, bit)| **bit) {
+                writeln!(f, " {}", service_name(i as u32))?;
+            }
+        }
+        writeln!(f, "}}\n")?;
+
+        writeln!(f, "Standard Object-Types Supported:")?;
+        writeln!(f, "{{")?;
+        if let Some(BACnetValue::BitString(bits)) =
+            property(bacnet_sys::BACNET_PROPERTY_ID_PROP_PROTOCOL_OBJECT_TYPES_SUPPORTED)
+        {
+            writeln!(f, "-- objects reported by this device")?;
+            for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+                writeln!(f, " {}", object_type_name(i as u32))?;
+            }
+        }
+        writeln!(f, "}}\n")?;
+
+        f.write_str(DATA_LINK_AND_CHARACTER_SETS)?;
+
+        writeln!(f, "Special Functionality:")?;
+        writeln!(f, "{{")?;
+        write!(f, " Maximum APDU size in octets: ")?;
+        match property(bacnet_sys::BACNET_PROPERTY_ID_PROP_MAX_APDU_LENGTH_ACCEPTED) {
+            Some(value) => write_value(f, "max-apdu-length-accepted", value)?,
+            None => write!(f, "?")?,
+        }
+        writeln!(f, "\n}}\n")?;
+
+        f.write_str(RESTRICTIONS_AND_FAIL_TIMES)
+    }
+
+    fn write_object(&self, f: &mut fmt::Formatter, object: &EpicsObject) -> fmt::Result {
+        let mut properties = object.properties.values().collect::<Vec<_>>();
+        properties.sort_by_key(|property| (property_order(property.id), property.id));
+        for property in properties {
+            let name = property.name.as_str();
+            let value = &property.value;
+            write!(f, "    {}: ", name)?;
+            if !self.show_values && is_volatile(name, value) {
+                write!(f, "?")?;
+            } else {
+                match (name, value) {
+                    ("protocol-object-types-supported", BACnetValue::BitString(bits)) => {
+                        write_supported_bits(f, bits, object_type_name)?
+                    }
+                    ("protocol-services-supported", BACnetValue::BitString(bits)) => {
+                        write_supported_bits(f, bits, service_name)?
+                    }
+                    (_, BACnetValue::Array(values)) if is_long_array(name) => {
+                        write_long_array(f, name, values)?
+                    }
+                    (_, BACnetValue::Array(values)) => {
+                        write!(f, "{{ ")?;
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, ",")?;
+                            }
+                            write_value(f, name, value)?;
+                        }
+                        write!(f, " }}")?;
+                    }
+                    (_, value) if is_long_array(name) => {
+                        write_long_array(f, name, std::slice::from_ref(value))?
+                    }
+                    (_, value) => write_value(f, name, value)?,
+                }
+            }
+            if is_writable(object.id.object_type, name) {
+                write!(f, " Writable")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for EpicsText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_header(f)?;
+
+        writeln!(f, "List of Objects in Test Device:")?;
+        writeln!(f, "{{")?;
+        writeln!(f, "  {{")?;
+        if let Some(device) = self.epics.device() {
+            self.write_object(f, device)?;
+        }
+
+        let objects = self
+            .epics
+            .objects
+            .values()
+            .filter(|object| object.id != self.epics.device_id)
+            .collect::<Vec<_>>();
+        writeln!(f, "  -- Found {} Objects ", objects.len() + 1)?;
+        for object in objects {
+            writeln!(f, "  }}, ")?;
+            writeln!(f, "  {{ ")?;
+            self.write_object(f, object)?;
+        }
+        writeln!(f, "  }} ")?;
+        writeln!(f, "}} ")?;
+        writeln!(
+            f,
+            "End of BACnet Protocol Implementation Conformance Statement"
+        )?;
+        writeln!(f)
+    }
+}
+
+// Maps of objects and properties are (de)serialized as a list of their values, which carry their
+// own keys; JSON only allows strings as keys.
+#[cfg(feature = "serde")]
+mod values {
+    use super::{EpicsObject, EpicsProperty, ObjectId};
+    use crate::ObjectPropertyId;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub(super) trait Keyed {
+        type Key: Ord;
+        fn key(&self) -> Self::Key;
+    }
+
+    impl Keyed for EpicsObject {
+        type Key = ObjectId;
+        fn key(&self) -> ObjectId {
+            self.id
+        }
+    }
+
+    impl Keyed for EpicsProperty {
+        type Key = ObjectPropertyId;
+        fn key(&self) -> ObjectPropertyId {
+            self.id
+        }
+    }
+
+    pub(super) fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.values())
+    }
+
+    pub(super) fn deserialize<'de, V, D>(deserializer: D) -> Result<BTreeMap<V::Key, V>, D::Error>
+    where
+        V: Keyed + Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<V>::deserialize(deserializer)?;
+        Ok(values
+            .into_iter()
+            .map(|value| (value.key(), value))
+            .collect())
+    }
+}
+
+// Write a value like bacapp_print_value() does
+fn write_value(f: &mut fmt::Formatter, property: &str, value: &BACnetValue) -> fmt::Result {
+    match value {
+        BACnetValue::Null => write!(f, "Null"),
+        BACnetValue::Bool(true) => write!(f, "TRUE"),
+        BACnetValue::Bool(false) => write!(f, "FALSE"),
+        BACnetValue::Uint(u) => write!(f, "{}", u),
+        BACnetValue::Int(i) => write!(f, "{}", i),
+        BACnetValue::Real(r) => write!(f, "{:.6}", r),
+        BACnetValue::Double(d) => write!(f, "{:.6}", d),
+        BACnetValue::String(s) => {
+            // VTS3 can't handle state texts longer than 31 characters
+            let chars = s.chars().collect::<Vec<_>>();
+            let s = if property == "state-text" && chars.len() > 31 {
+                let mut shortened = chars[..15].iter().collect::<String>();
+                shortened.push('-');
+                shortened.extend(&chars[chars.len() - 15..]);
+                shortened
+            } else {
+                s.clone()
+            };
+            let printable = s
+                .chars()
+                .map(|c| if c.is_control() { '.' } else { c })
+                .collect::<String>();
+            write!(f, "\"{}\"", printable)
+        }
+        BACnetValue::Bytes(bytes) => {
+            for b in bytes {
+                write!(f, "{:02X}", b)?;
+            }
+            Ok(())
+        }
+        BACnetValue::BitString(bits) => {
+            write!(f, "{{")?;
+            for (i, bit) in bits.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}", bit)?;
+            }
+            write!(f, "}}")
+        }
+        BACnetValue::Enum(_, Some(name)) => write!(f, "{}", name),
+        BACnetValue::Enum(e, None) => write!(f, "{}", e),
+        BACnetValue::ObjectId {
+            object_type,
+            object_instance,
+        } => {
+            if *object_type < bacnet_sys::MAX_ASHRAE_OBJECT_TYPE {
+                write!(
+                    f,
+                    "({}, {})",
+                    object_type_name(*object_type),
+                    object_instance
+                )
+            } else if *object_type < 128 {
+                write!(f, "(reserved {}, {})", object_type, object_instance)
+            } else {
+                write!(f, "(proprietary {}, {})", object_type, object_instance)
+            }
+        }
+        BACnetValue::Array(values) => {
+            write!(f, "{{")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write_value(f, property, value)?;
+            }
+            write!(f, "}}")
+        }
+    }
+}
+
+// The object-list and friends, three elements to a line
+fn write_long_array(f: &mut fmt::Formatter, property: &str, values: &[BACnetValue]) -> fmt::Result {
+    if values.is_empty() || values[0] == BACnetValue::Null {
+        return write!(f, "?");
+    }
+    write!(f, "{{ \n        ")?;
+    for (i, value) in values.iter().enumerate() {
+        let sequence = property == "subordinate-list";
+        if sequence {
+            write!(f, "{{")?;
+        }
+        write_value(f, property, value)?;
+        if sequence {
+            write!(f, "}}")?;
+        }
+        if i + 1 < values.len() {
+            write!(f, ", ")?;
+            if (i + 1) % 3 == 0 {
+                write!(f, "\n        ")?;
+            }
+        } else {
+            write!(f, " }} ")?;
+        }
+    }
+    Ok(())
+}
+
+// protocol-object-types-supported and protocol-services-supported as T and F, four to a line,
+// with the names of the supported ones in a comment
+fn write_supported_bits(
+    f: &mut fmt::Formatter,
+    bits: &[bool],
+    name: fn(u32) -> String,
+) -> fmt::Result {
+    write!(f, "( \n        ")?;
+    for (i, bit) in bits.iter().enumerate() {
+        write!(f, "{}", if *bit { "T" } else { "F" })?;
+        let last = i + 1 == bits.len();
+        write!(f, "{}", if last { " " } else { "," })?;
+        if last || i % 4 == 3 {
+            write!(f, "   -- ")?;
+            let first = i - (i % 4);
+            for (j, bit) in bits.iter().enumerate().take(i + 1).skip(first) {
+                if *bit {
+                    write!(f, " {},", name(j as u32))?;
+                } else {
+                    write!(f, ",")?;
+                }
+            }
+            write!(f, "\n        ")?;
+        }
+    }
+    write!(f, ") ")
+}
+
+// Properties that change while the device runs
+const VOLATILE_PROPERTIES: &[&str] = &[
+    "daylight-savings-status",
+    "local-time",
+    "local-date",
+    "present-value",
+    "priority-array",
+    "reliability",
+    "utc-offset",
+    "database-revision",
+];
+
+// Properties that are written as `?` unless asked otherwise
+fn is_volatile(property: &str, value: &BACnetValue) -> bool {
+    match property {
+        "device-address-binding" => *value == BACnetValue::Null,
+        _ => VOLATILE_PROPERTIES.contains(&property),
+    }
+}
+
+fn is_long_array(property: &str) -> bool {
+    matches!(
+        property,
+        "object-list"
+            | "state-text"
+            | "structured-object-list"
+            | "subordinate-annotations"
+            | "subordinate-list"
+    )
+}
+
+// Properties that are writable by definition, the most common ones checked by
+// CheckIsWritableProperty() in bacepics
+fn is_writable(object_type: u32, property: &str) -> bool {
+    match object_type {
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_OUTPUT
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_OUTPUT
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_COMMAND
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_OUTPUT
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ACCESS_DOOR => property == "present-value",
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_FILE => property == "archive",
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_LIFE_SAFETY_POINT
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_LIFE_SAFETY_ZONE => property == "mode",
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_PROGRAM => property == "program-change",
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_TRENDLOG
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_EVENT_LOG
+        | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_TREND_LOG_MULTIPLE => {
+            property == "enable" || property == "record-count"
+        }
+        _ => false,
+    }
+}
+
+// The identifying properties first, and the object lists last, like bacepics does
+fn property_order(property: ObjectPropertyId) -> u8 {
+    match property {
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER => 0,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME => 1,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_TYPE => 2,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_STRUCTURED_OBJECT_LIST => 4,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST => 5,
+        _ => 3,
+    }
+}
+
+pub(crate) fn object_type_name(object_type: u32) -> String {
+    // The stack names them all "Vendor Proprietary Value"
+    if object_type >= bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_PROPRIETARY_MIN {
+        format!("proprietary-{}", object_type)
+    } else {
+        crate::cstr(unsafe { bacnet_sys::bactext_object_type_name(object_type) })
+    }
+}
+
+// The name of a bit of protocol-services-supported
+pub(crate) fn service_name(service: u32) -> String {
+    let mut index = 0;
+    let mut confirmed = false;
+    let found =
+        unsafe { bacnet_sys::apdu_service_supported_to_index(service, &mut index, &mut confirmed) };
+    if !found {
+        return "unknown".to_string();
+    }
+    crate::cstr(unsafe {
+        if confirmed {
+            bacnet_sys::bactext_confirmed_service_name(index as u32)
+        } else {
+            bacnet_sys::bactext_unconfirmed_service_name(index as u32)
+        }
+    })
+}
+
+// The BIBBs a device supports as a server (B side) when it executes the given service
+const BIBBS: &[(u32, &str)] = &[
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_PROPERTY,
+        "DS-RP-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_READ_PROP_MULTIPLE,
+        "DS-RPM-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WRITE_PROPERTY,
+        "DS-WP-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WRITE_PROP_MULTIPLE,
+        "DS-WPM-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_SUBSCRIBE_COV,
+        "DS-COV-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_SUBSCRIBE_COV_PROPERTY,
+        "DS-COVP-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_ACKNOWLEDGE_ALARM,
+        "AE-ACK-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_ALARM_SUMMARY,
+        "AE-ASUM-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_ENROLLMENT_SUMMARY,
+        "AE-ESUM-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_GET_EVENT_INFORMATION,
+        "AE-INFO-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WHO_IS,
+        "DM-DDB-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_WHO_HAS,
+        "DM-DOB-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_DEVICE_COMMUNICATION_CONTROL,
+        "DM-DCC-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_PRIVATE_TRANSFER,
+        "DM-PT-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_TIME_SYNCHRONIZATION,
+        "DM-TS-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_UTC_TIME_SYNCHRONIZATION,
+        "DM-UTC-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_REINITIALIZE_DEVICE,
+        "DM-RD-B",
+    ),
+    (
+        bacnet_sys::BACNET_SERVICES_SUPPORTED_SERVICE_SUPPORTED_CREATE_OBJECT,
+        "DM-OCD-B",
+    ),
+];
+
+// The sections bacepics can't know about, left for the vendor to fill in
+const DATA_LINK_AND_CHARACTER_SETS: &str = "\
+Data Link Layer Option:
+{
+-- choose the data link options supported
+-- ISO 8802-3, 10BASE5
+-- ISO 8802-3, 10BASE2
+-- ISO 8802-3, 10BASET
+-- ISO 8802-3, fiber
+-- ARCNET, coax star
+-- ARCNET, coax bus
+-- ARCNET, twisted pair star
+-- ARCNET, twisted pair bus
+-- ARCNET, fiber star
+-- ARCNET, twisted pair, EIA-485, Baud rate(s): 156000
+-- MS/TP master. Baud rate(s): 9600, 38400
+-- MS/TP slave. Baud rate(s): 9600, 38400
+-- Point-To-Point. EIA 232, Baud rate(s): 9600
+-- Point-To-Point. Modem, Baud rate(s): 9600
+-- Point-To-Point. Modem, Baud rate(s): 9600 to 115200
+-- BACnet/IP, 'DIX' Ethernet
+-- BACnet/IP, Other
+-- Other
+}
+
+Character Sets Supported:
+{
+-- choose any character sets supported
+-- ANSI X3.4
+-- IBM/Microsoft DBCS
+-- JIS C 6226
+-- ISO 8859-1
+-- ISO 10646 (UCS-4)
+-- ISO 10646 (UCS2)
+}
+
+";
+
+const RESTRICTIONS_AND_FAIL_TIMES: &str = "\
+Default Property Value Restrictions:
+{
+  unsigned-integer: <minimum: 0; maximum: 4294967295>
+  signed-integer: <minimum: -2147483647; maximum: 2147483647>
+  real: <minimum: -3.40282347E38; maximum: 3.40282347E38; resolution: 1.0>
+  double: <minimum: 2.2250738585072016E-38; maximum: 1.7976931348623157E38; resolution: 0.0001>
+  date: <minimum: 01-January-1970; maximum: 31-December-2038>
+  octet-string: <maximum length string: 122>
+  character-string: <maximum length string: 122>
+  list: <maximum length list: 10>
+  variable-length-array: <maximum length array: 10>
+}
+
+Fail Times:
+{
+  Notification Fail Time: 2
+  Internal Processing Fail Time: 0.5
+  Minimum ON/OFF Time: 5
+  Schedule Evaluation Fail Time: 1
+  External Command Fail Time: 1
+  Program Object State Change Fail Time: 2
+  Acknowledgement Fail Time: 2
+}
+
+";
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::{Epics, EpicsObject, ObjectId};
+    use crate::value::BACnetValue;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn objects_and_properties_are_lists() {
+        let device_id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE, 1234);
+        let input_id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_INPUT, 1);
+        let device = EpicsObject::new(
+            device_id,
+            HashMap::from([(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME,
+                BACnetValue::String("Gateway".to_string()),
+            )]),
+        );
+        let input = EpicsObject::new(
+            input_id,
+            HashMap::from([
+                (
+                    bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+                    BACnetValue::Real(21.5),
+                ),
+                (
+                    bacnet_sys::BACNET_PROPERTY_ID_PROP_UNITS,
+                    BACnetValue::Enum(62, Some("degrees-celsius".to_string())),
+                ),
+            ]),
+        );
+        let epics = Epics {
+            device_id,
+            objects: vec![(device_id, device), (input_id, input)]
+                .into_iter()
+                .collect(),
+        };
+
+        let json = serde_json::to_value(&epics).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "device_id": {"object_type": 8, "object_instance": 1234},
+                "objects": [
+                    {
+                        "id": {"object_type": 0, "object_instance": 1},
+                        "properties": [
+                            {
+                                "id": 85,
+                                "name": "present-value",
+                                "value": {"type": "real", "value": 21.5},
+                            },
+                            {
+                                "id": 117,
+                                "name": "units",
+                                "value": {
+                                    "type": "enum",
+                                    "value": {"value": 62, "name": "degrees-celsius"},
+                                },
+                            },
+                        ],
+                    },
+                    {
+                        "id": {"object_type": 8, "object_instance": 1234},
+                        "properties": [
+                            {
+                                "id": 77,
+                                "name": "object-name",
+                                "value": {"type": "string", "value": "Gateway"},
+                            },
+                        ],
+                    },
+                ],
+            })
+        );
+
+        let parsed: Epics = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.device_id, epics.device_id);
+        assert_eq!(parsed.objects, epics.objects);
+    }
 }
@@ -0,0 +1,286 @@
+// Differences between two `Epics` of a device, e.g. before and after a firmware upgrade
+
+use std::fmt;
+
+use super::{write_value, Epics, EpicsObject, EpicsProperty, ObjectId, VOLATILE_PROPERTIES};
+use crate::value::BACnetValue;
+use crate::ObjectPropertyId;
+
+/// The differences between two `Epics`, from `Epics::diff()`. Written as a report with `Display`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpicsDiff {
+    /// Objects only in the newer `Epics`
+    pub added: Vec<ObjectId>,
+    /// Objects only in the older `Epics`
+    pub removed: Vec<ObjectId>,
+    /// Objects in both, with different properties
+    pub changed: Vec<ObjectDiff>,
+}
+
+/// The differences between the properties of an object in two `Epics`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectDiff {
+    pub id: ObjectId,
+    pub added: Vec<EpicsProperty>,
+    pub removed: Vec<EpicsProperty>,
+    pub changed: Vec<PropertyChange>,
+}
+
+/// A property with different values in two `Epics`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropertyChange {
+    pub id: ObjectPropertyId,
+    pub name: String,
+    pub old: BACnetValue,
+    pub new: BACnetValue,
+}
+
+impl Epics {
+    /// The differences from this `Epics` to a newer one of the same device.
+    ///
+    /// Values are compared like they're written in an EPICS document: enumerations by their
+    /// value only, numbers to 6 decimals whatever their type, and strings as shortened and with
+    /// control characters replaced. That way a scanned `Epics` can be compared with one parsed
+    /// from a document.
+    // let diff = before.diff(&after).ignore_volatile();
+    pub fn diff(&self, newer: &Epics) -> EpicsDiff {
+        let mut diff = EpicsDiff::default();
+        for (id, old) in &self.objects {
+            match newer.objects.get(id) {
+                Some(new) => {
+                    let object = diff_object(old, new);
+                    if !object.is_empty() {
+                        diff.changed.push(object);
+                    }
+                }
+                None => diff.removed.push(*id),
+            }
+        }
+        diff.added = newer
+            .objects
+            .keys()
+            .filter(|id| !self.objects.contains_key(id))
+            .copied()
+            .collect();
+        diff
+    }
+}
+
+impl EpicsDiff {
+    /// Leave out the properties that change while the device runs, like present-value and
+    /// local-time
+    pub fn ignore_volatile(mut self) -> Self {
+        for object in &mut self.changed {
+            object.added.retain(|property| !is_volatile(&property.name));
+            object
+                .removed
+                .retain(|property| !is_volatile(&property.name));
+            object.changed.retain(|change| !is_volatile(&change.name));
+        }
+        self.changed.retain(|object| !object.is_empty());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl ObjectDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for EpicsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+        for id in &self.added {
+            writeln!(f, "+ {}", id)?;
+        }
+        for id in &self.removed {
+            writeln!(f, "- {}", id)?;
+        }
+        for object in &self.changed {
+            writeln!(f, "~ {}", object.id)?;
+            for property in &object.added {
+                write!(f, "    + {}: ", property.name)?;
+                write_value(f, &property.name, &property.value)?;
+                writeln!(f)?;
+            }
+            for property in &object.removed {
+                write!(f, "    - {}: ", property.name)?;
+                write_value(f, &property.name, &property.value)?;
+                writeln!(f)?;
+            }
+            for change in &object.changed {
+                write!(f, "    ~ {}: ", change.name)?;
+                write_value(f, &change.name, &change.old)?;
+                write!(f, " -> ")?;
+                write_value(f, &change.name, &change.new)?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn diff_object(old: &EpicsObject, new: &EpicsObject) -> ObjectDiff {
+    let mut diff = ObjectDiff {
+        id: old.id,
+        added: vec![],
+        removed: vec![],
+        changed: vec![],
+    };
+    for (id, old) in &old.properties {
+        match new.properties.get(id) {
+            Some(new) if !same_value(&old.name, &old.value, &new.value) => {
+                diff.changed.push(PropertyChange {
+                    id: *id,
+                    name: old.name.clone(),
+                    old: old.value.clone(),
+                    new: new.value.clone(),
+                })
+            }
+            Some(_) => {}
+            None => diff.removed.push(old.clone()),
+        }
+    }
+    diff.added = new
+        .properties
+        .iter()
+        .filter(|(id, _)| !old.properties.contains_key(id))
+        .map(|(_, property)| property.clone())
+        .collect();
+    diff
+}
+
+// Values are compared like they're written in an EPICS document, so the numbers, enumerations
+// and strings that look the same there are the same
+fn same_value(property: &str, old: &BACnetValue, new: &BACnetValue) -> bool {
+    use BACnetValue::*;
+    match (old, new) {
+        (Enum(old, _), Enum(new, _)) => old == new,
+        (Enum(e, _), Uint(u)) | (Uint(u), Enum(e, _)) => u64::from(*e) == *u,
+        (String(_), String(_)) => {
+            Written(property, old).to_string() == Written(property, new).to_string()
+        }
+        (Array(old), Array(new)) => {
+            old.len() == new.len()
+                && old
+                    .iter()
+                    .zip(new)
+                    .all(|(old, new)| same_value(property, old, new))
+        }
+        (old, new) => match (as_f64(old), as_f64(new)) {
+            (Some(old), Some(new)) => format!("{:.6}", old) == format!("{:.6}", new),
+            _ => old == new,
+        },
+    }
+}
+
+fn as_f64(value: &BACnetValue) -> Option<f64> {
+    match value {
+        BACnetValue::Uint(u) => Some(*u as f64),
+        BACnetValue::Int(i) => Some(f64::from(*i)),
+        BACnetValue::Real(r) => Some(f64::from(*r)),
+        BACnetValue::Double(d) => Some(*d),
+        _ => None,
+    }
+}
+
+// A value as written in an EPICS document
+struct Written<'a>(&'a str, &'a BACnetValue);
+
+impl<'a> fmt::Display for Written<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self.0, self.1)
+    }
+}
+
+fn is_volatile(property: &str) -> bool {
+    property == "device-address-binding" || VOLATILE_PROPERTIES.contains(&property)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BACnetValue::{Array, Double, Enum, Int, Real, String, Uint};
+
+    #[test]
+    fn numbers_of_different_types() {
+        assert!(same_value("present-value", &Uint(72), &Real(72.0)));
+        assert!(same_value("present-value", &Int(-3), &Double(-3.0)));
+        assert!(same_value("present-value", &Real(0.1), &Double(0.1)));
+        assert!(!same_value("present-value", &Uint(72), &Real(72.5)));
+    }
+
+    #[test]
+    fn enumerations_by_value() {
+        assert!(same_value(
+            "units",
+            &Enum(62, Some("degrees-celsius".to_string())),
+            &Enum(62, None)
+        ));
+        assert!(same_value("present-value", &Enum(3, None), &Uint(3)));
+        assert!(same_value("present-value", &Uint(1), &Enum(1, None)));
+        assert!(!same_value("present-value", &Enum(1, None), &Uint(0)));
+    }
+
+    #[test]
+    fn strings_as_written() {
+        let long = "A state text that is much longer than 31 characters";
+        let written = "A state text th-n 31 characters";
+        assert_eq!(
+            Written("state-text", &String(long.to_string())).to_string(),
+            format!("\"{}\"", written)
+        );
+        assert!(same_value(
+            "state-text",
+            &Array(vec![String(long.to_string())]),
+            &Array(vec![String(written.to_string())])
+        ));
+        assert!(same_value(
+            "description",
+            &String("line\nbreak".to_string()),
+            &String("line.break".to_string())
+        ));
+        assert!(!same_value(
+            "object-name",
+            &String("Fan".to_string()),
+            &String("Pump".to_string())
+        ));
+    }
+
+    #[test]
+    fn diff_reports_only_real_changes() {
+        let id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_VALUE, 1);
+        let object = |present_value, description: &str| {
+            let mut properties = std::collections::HashMap::new();
+            properties.insert(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+                present_value,
+            );
+            properties.insert(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_DESCRIPTION,
+                String(description.to_string()),
+            );
+            let mut epics = Epics::default();
+            epics.objects.insert(id, EpicsObject::new(id, properties));
+            epics
+        };
+        let scanned = object(Uint(3), "Mode");
+        let parsed = object(Enum(3, None), "Mode");
+        assert!(scanned.diff(&parsed).is_empty());
+
+        let diff = scanned.diff(&object(Uint(3), "Fan mode"));
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].changed.len(), 1);
+        assert_eq!(diff.changed[0].changed[0].name, "description");
+    }
+}
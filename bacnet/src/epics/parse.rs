@@ -0,0 +1,886 @@
+// Parsing the list of objects of an EPICS text document, as written by `EpicsText`, bacepics or
+// by hand
+//
+// Values are mapped to `BACnetValue` like this:
+// - `?` (a value that isn't given) is `Null`
+// - `{...}` and `(...)` lists are `Array`s, except that `(type, instance)` is an `ObjectId`, and
+//   lists of only `T`/`F` or `true`/`false` are `BitString`s
+// - enumerations are given by name, and are looked up with the stack's bactext tables
+// - dates and times, which `BACnetValue` can't hold, are kept as `String`s
+// - any other value that can't be parsed (e.g. an unknown enumeration) is kept as a `String` of
+//   its text, with a warning
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::str::FromStr;
+
+use super::{Epics, EpicsObject, EpicsProperty, ObjectId, PROPRIETARY_MIN};
+use crate::value::BACnetValue;
+use crate::{Error, ObjectPropertyId, Result};
+
+const OBJECT_LIST_HEADER: &str = "List of Objects in Test Device:";
+
+impl FromStr for Epics {
+    type Err = Error;
+
+    /// Parse the list of objects of an EPICS document. The rest of the document (BIBBs, services,
+    /// etc.) is skipped, as it's derived from the device object.
+    fn from_str(s: &str) -> Result<Self> {
+        let start = s.find(OBJECT_LIST_HEADER).ok_or(Error::InvalidEpics {
+            line: 0,
+            reason: format!("no \"{}\"", OBJECT_LIST_HEADER),
+        })?;
+        let first_line = s[..start].matches('\n').count() + 1;
+        let tokens = tokenize(&s[start + OBJECT_LIST_HEADER.len()..], first_line)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            line: first_line,
+        };
+
+        let mut objects = BTreeMap::new();
+        let mut device_id = None;
+        for object in parser.objects()? {
+            if object.id.object_type == bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE {
+                device_id = Some(object.id);
+            }
+            objects.insert(object.id, object);
+        }
+        let device_id = device_id.ok_or(Error::InvalidEpics {
+            line: parser.line,
+            reason: "no device object".to_string(),
+        })?;
+        Ok(Epics { device_id, objects })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open(char),
+    Close(char),
+    Comma,
+    Colon,
+    Unknown,
+    String(String),
+    Bytes(Vec<u8>),
+    Word(String),
+}
+
+fn tokenize(s: &str, first_line: usize) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut line = first_line;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '-' if chars.peek() == Some(&'-') => {
+                // A comment, up to the end of the line
+                while matches!(chars.peek(), Some(c) if *c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '{' | '(' => Token::Open(c),
+            '}' | ')' => Token::Close(c),
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '?' => Token::Unknown,
+            '"' | '\'' => Token::String(quoted(&mut chars, c, &mut line)?),
+            'X' | 'x' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let hex = quoted(&mut chars, '\'', &mut line)?;
+                Token::Bytes(parse_hex(&hex).ok_or(Error::InvalidEpics {
+                    line,
+                    reason: format!("invalid octet string X'{}'", hex),
+                })?)
+            }
+            c => {
+                // Times like 12:30:00.00 or **:**:**.** contain colons
+                let time = c.is_ascii_digit() || c == '*';
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || "{}(),\"'?".contains(*c) || (*c == ':' && !time) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((token, line));
+    }
+    Ok(tokens)
+}
+
+// The rest of a string started with `quote`
+fn quoted<I>(chars: &mut I, quote: char, line: &mut usize) -> Result<String>
+where
+    I: Iterator<Item = char>,
+{
+    let start = *line;
+    let mut s = String::new();
+    for c in chars {
+        if c == quote {
+            return Ok(s);
+        }
+        if c == '\n' {
+            *line += 1;
+        }
+        s.push(c);
+    }
+    Err(Error::InvalidEpics {
+        line: start,
+        reason: "unterminated string".to_string(),
+    })
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // The line of the last token taken, for errors
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, reason: String) -> Error {
+        Error::InvalidEpics {
+            line: self.line,
+            reason,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        match self.tokens.get(self.pos) {
+            Some((token, line)) => {
+                self.pos += 1;
+                self.line = *line;
+                Ok(token.clone())
+            }
+            None => Err(self.error("unexpected end of the document".to_string())),
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {:?}, found {:?}", expected, token)))
+        }
+    }
+
+    // { { object }, { object }, ... }
+    fn objects(&mut self) -> Result<Vec<EpicsObject>> {
+        self.expect(Token::Open('{'))?;
+        let mut objects = Vec::new();
+        loop {
+            match self.next()? {
+                Token::Open('{') => objects.push(self.object()?),
+                Token::Comma => {}
+                Token::Close('}') => return Ok(objects),
+                token => return Err(self.error(format!("expected an object, found {:?}", token))),
+            }
+        }
+    }
+
+    // The properties of an object, after its opening brace
+    fn object(&mut self) -> Result<EpicsObject> {
+        let start = self.line;
+        let mut properties = BTreeMap::new();
+        loop {
+            let name = match self.next()? {
+                Token::Close('}') => break,
+                Token::Word(name) => name,
+                token => return Err(self.error(format!("expected a property, found {:?}", token))),
+            };
+            self.expect(Token::Colon)?;
+            let value_start = self.pos;
+            let value = match self.value(&name) {
+                Ok(value) if self.skip_flags() => value,
+                result => {
+                    // Keep what we can't parse, like a date or an enumeration we don't know
+                    self.pos = value_start;
+                    let text = self.text()?;
+                    match result {
+                        Err(err) => warn!("line {}: keeping {} as text: {}", self.line, name, err),
+                        Ok(_) => warn!("line {}: keeping {} as text", self.line, name),
+                    }
+                    BACnetValue::String(text)
+                }
+            };
+
+            match property_id(&name) {
+                Some(id) => {
+                    properties.insert(id, EpicsProperty::new(id, value));
+                }
+                None => warn!("line {}: skipping unknown property {}", self.line, name),
+            }
+        }
+
+        let id = match properties
+            .get(&bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER)
+            .map(|property| &property.value)
+        {
+            Some(BACnetValue::ObjectId {
+                object_type,
+                object_instance,
+            }) => ObjectId::new(*object_type, *object_instance),
+            _ => {
+                return Err(Error::InvalidEpics {
+                    line: start,
+                    reason: "object without an object-identifier".to_string(),
+                })
+            }
+        };
+        // A number is only a BACnetBinaryPV on binary objects, and e.g. an Unsigned on multi-state
+        // ones
+        if is_binary_object(id.object_type) {
+            for property in properties.values_mut() {
+                if is_binary_pv(&property.name) {
+                    property.value = binary_pv(property.value.clone());
+                }
+            }
+        }
+        Ok(EpicsObject { id, properties })
+    }
+
+    // Whether the next tokens start a property, e.g. `present-value:`
+    fn at_property(&self) -> bool {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(Token::Word(name)), Some(Token::Colon)) => {
+                name.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }
+            _ => false,
+        }
+    }
+
+    // Skip flags like W (writable) or R, up to the next property. Returns false if there's
+    // anything else before it, i.e. the value didn't end where it was expected to.
+    fn skip_flags(&mut self) -> bool {
+        loop {
+            match self.peek() {
+                _ if self.at_property() => return true,
+                Some(Token::Close('}')) => return true,
+                Some(Token::Word(_)) => self.pos += 1,
+                _ => return false,
+            }
+        }
+    }
+
+    // The text of a value, up to the next property or the end of the object, without the flags
+    fn text(&mut self) -> Result<String> {
+        let mut words = Vec::new();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                _ if depth == 0 && self.at_property() => break,
+                Some(Token::Close('}')) if depth == 0 => break,
+                Some(Token::Open(_)) => depth += 1,
+                Some(Token::Close(_)) => depth -= 1,
+                _ => {}
+            }
+            words.push(self.next()?);
+        }
+        while matches!(words.last(), Some(Token::Word(flag)) if flag == "W" || flag == "Writable") {
+            words.pop();
+        }
+
+        let mut text = String::new();
+        for token in words {
+            match token {
+                Token::Comma => text.push(','),
+                Token::Colon => text.push(':'),
+                Token::Close(c) => text.push(c),
+                token => {
+                    if !text.is_empty() && !text.ends_with(['(', '{']) {
+                        text.push(' ');
+                    }
+                    match token {
+                        Token::Open(c) => text.push(c),
+                        Token::Unknown => text.push('?'),
+                        Token::String(s) => text.push_str(&format!("\"{}\"", s)),
+                        Token::Bytes(bytes) => {
+                            text.push_str("X'");
+                            for b in bytes {
+                                text.push_str(&format!("{:02X}", b));
+                            }
+                            text.push('\'');
+                        }
+                        Token::Word(word) => text.push_str(&word),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if text.is_empty() {
+            return Err(self.error("expected a value".to_string()));
+        }
+        Ok(text)
+    }
+
+    fn value(&mut self, property: &str) -> Result<BACnetValue> {
+        match self.next()? {
+            Token::Unknown => Ok(BACnetValue::Null),
+            Token::String(s) => Ok(BACnetValue::String(s)),
+            Token::Bytes(bytes) => Ok(BACnetValue::Bytes(bytes)),
+            Token::Word(word) => self.word(property, &word),
+            Token::Open('(') => {
+                if let Some(value) = self.object_id()? {
+                    return Ok(value);
+                }
+                self.list(property, ')', &["T", "F"])
+            }
+            Token::Open('{') => {
+                let value = self.list(property, '}', &["T", "F", "true", "false"])?;
+                // subordinate-list is written as a list of {(type, instance)}
+                match value {
+                    BACnetValue::Array(values) if property == "subordinate-list" => {
+                        Ok(BACnetValue::Array(
+                            values
+                                .into_iter()
+                                .map(|value| match value {
+                                    BACnetValue::Array(mut inner) if inner.len() == 1 => {
+                                        inner.remove(0)
+                                    }
+                                    value => value,
+                                })
+                                .collect(),
+                        ))
+                    }
+                    value => Ok(value),
+                }
+            }
+            token => Err(self.error(format!("expected a value, found {:?}", token))),
+        }
+    }
+
+    // The elements of a list, after its opening bracket. Lists of only `bits` are bit strings.
+    fn list(&mut self, property: &str, close: char, bits: &[&str]) -> Result<BACnetValue> {
+        let mut words = Vec::new();
+        let start = self.pos;
+        loop {
+            match self.next()? {
+                Token::Word(word) if bits.contains(&word.as_str()) => words.push(word),
+                Token::Comma => {}
+                Token::Close(c) if c == close && !words.is_empty() => {
+                    return Ok(BACnetValue::BitString(
+                        words
+                            .iter()
+                            .map(|bit| bit == "T" || bit == "true")
+                            .collect(),
+                    ))
+                }
+                _ => break,
+            }
+        }
+
+        self.pos = start;
+        let mut values = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Close(c)) if *c == close => {
+                    self.next()?;
+                    return Ok(BACnetValue::Array(values));
+                }
+                Some(Token::Comma) => {
+                    self.next()?;
+                }
+                _ => values.push(self.value(property)?),
+            }
+        }
+    }
+
+    // (analog-input, 1), (proprietary 130, 1) or (reserved 60, 1), after the opening parenthesis
+    fn object_id(&mut self) -> Result<Option<BACnetValue>> {
+        let (object_type, len) = match (self.peek(), self.peek_at(1)) {
+            (Some(Token::Word(kind)), Some(Token::Word(number)))
+                if kind == "proprietary" || kind == "reserved" =>
+            {
+                match number.parse() {
+                    Ok(object_type) => (object_type, 2),
+                    Err(_) => return Ok(None),
+                }
+            }
+            (Some(Token::Word(name)), Some(Token::Comma)) => match object_type(name) {
+                Some(object_type) => (object_type, 1),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let object_instance = match (self.peek_at(len), self.peek_at(len + 1)) {
+            (Some(Token::Comma), Some(Token::Word(number))) => match number.parse() {
+                Ok(instance) => instance,
+                Err(_) => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        if self.peek_at(len + 2) != Some(&Token::Close(')')) {
+            return Ok(None);
+        }
+        self.pos += len + 3;
+        self.line = self.tokens[self.pos - 1].1;
+        Ok(Some(BACnetValue::ObjectId {
+            object_type,
+            object_instance,
+        }))
+    }
+
+    fn word(&self, property: &str, word: &str) -> Result<BACnetValue> {
+        if word.eq_ignore_ascii_case("true") {
+            return Ok(BACnetValue::Bool(true));
+        }
+        if word.eq_ignore_ascii_case("false") {
+            return Ok(BACnetValue::Bool(false));
+        }
+        if word.eq_ignore_ascii_case("null") {
+            return Ok(BACnetValue::Null);
+        }
+        if let Ok(u) = word.parse::<u64>() {
+            if property == "property-list"
+                || (enum_names(property).is_some() && !is_binary_pv(property))
+            {
+                return Ok(BACnetValue::Enum(u as u32, None));
+            }
+            return Ok(BACnetValue::Uint(u));
+        }
+        if let Ok(i) = word.parse::<i32>() {
+            return Ok(BACnetValue::Int(i));
+        }
+        let numeric =
+            word.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.' || c == '*');
+        if numeric {
+            if let Ok(r) = word.parse::<f32>() {
+                return Ok(BACnetValue::Real(r));
+            }
+        }
+        if word.chars().any(|c| c.is_ascii_alphabetic()) {
+            if let Some(bytes) = parse_hex(word) {
+                return Ok(BACnetValue::Bytes(bytes));
+            }
+        }
+        if numeric {
+            // A date or time, possibly with wildcards
+            return Ok(BACnetValue::String(word.to_string()));
+        }
+        match enum_value(property, word) {
+            Some(value) => Ok(BACnetValue::Enum(value, Some(word.to_string()))),
+            None => Err(self.error(format!("unknown value {} of {}", word, property))),
+        }
+    }
+}
+
+// Properties that hold a BACnetBinaryPV on binary objects
+fn is_binary_pv(property: &str) -> bool {
+    matches!(
+        property,
+        "present-value"
+            | "relinquish-default"
+            | "alarm-value"
+            | "feedback-value"
+            | "priority-array"
+    )
+}
+
+fn is_binary_object(object_type: u32) -> bool {
+    matches!(
+        object_type,
+        bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_INPUT
+            | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_OUTPUT
+            | bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_VALUE
+    )
+}
+
+fn binary_pv(value: BACnetValue) -> BACnetValue {
+    match value {
+        BACnetValue::Uint(u) => BACnetValue::Enum(u as u32, None),
+        BACnetValue::Array(values) => {
+            BACnetValue::Array(values.into_iter().map(binary_pv).collect())
+        }
+        value => value,
+    }
+}
+
+fn property_id(name: &str) -> Option<ObjectPropertyId> {
+    if let Some(id) = name.strip_prefix("proprietary-") {
+        return id.parse().ok().filter(|id| *id >= PROPRIETARY_MIN);
+    }
+    lookup(bacnet_sys::bactext_property_index, name)
+}
+
+fn object_type(name: &str) -> Option<u32> {
+    match name.strip_prefix("proprietary-") {
+        Some(object_type) => object_type.parse().ok(),
+        None => lookup(bacnet_sys::bactext_object_type_index, name),
+    }
+}
+
+type IndexFn = unsafe extern "C" fn(*const c_char, *mut u32) -> bool;
+type NameFn = unsafe extern "C" fn(u32) -> *const c_char;
+
+fn lookup(index: IndexFn, name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut found = 0;
+    if unsafe { index(name.as_ptr(), &mut found) } {
+        Some(found)
+    } else {
+        None
+    }
+}
+
+// The values of enumerations without an index function in bactext are looked up by name
+fn lookup_name(names: NameFn, name: &str) -> Option<u32> {
+    (0..256).find(|i| crate::cstr(unsafe { names(*i) }) == name)
+}
+
+// How the names of the values of an enumerated property are looked up
+enum EnumNames {
+    Index(IndexFn),
+    Names(NameFn),
+    // Names of the values 0, 1, ..., for enumerations bactext has no names for
+    Table(&'static [&'static str]),
+}
+
+const NOTIFY_TYPE_NAMES: &[&str] = &["alarm", "event", "ack-notification"];
+
+const EVENT_TYPE_NAMES: &[&str] = &[
+    "change-of-bitstring",
+    "change-of-state",
+    "change-of-value",
+    "command-failure",
+    "floating-limit",
+    "out-of-range",
+    "complex-event-type",
+    "",
+    "change-of-life-safety",
+    "extended",
+    "buffer-ready",
+    "unsigned-range",
+    "",
+    "access-event",
+    "double-out-of-range",
+    "signed-out-of-range",
+    "unsigned-out-of-range",
+    "change-of-characterstring",
+    "change-of-status-flags",
+    "change-of-reliability",
+    "none",
+    "change-of-discrete-value",
+    "change-of-timer",
+];
+
+fn enum_names(property: &str) -> Option<EnumNames> {
+    use EnumNames::*;
+    Some(match property {
+        "units" => Index(bacnet_sys::bactext_engineering_unit_index),
+        "object-type" => Index(bacnet_sys::bactext_object_type_index),
+        "segmentation-supported" => Index(bacnet_sys::bactext_segmentation_index),
+        "present-value" | "relinquish-default" | "alarm-value" | "feedback-value" => {
+            Index(bacnet_sys::bactext_binary_present_value_index)
+        }
+        "event-state" => Names(bacnet_sys::bactext_event_state_name),
+        "reliability" => Names(bacnet_sys::bactext_reliability_name),
+        "system-status" => Names(bacnet_sys::bactext_device_status_name),
+        "polarity" => Names(bacnet_sys::bactext_binary_polarity_name),
+        "node-type" => Names(bacnet_sys::bactext_node_type_name),
+        "life-safety-state" => Names(bacnet_sys::bactext_life_safety_state_name),
+        "notify-type" => Table(NOTIFY_TYPE_NAMES),
+        "event-type" => Table(EVENT_TYPE_NAMES),
+        _ => return None,
+    })
+}
+
+// The value of an enumeration, looked up in the names for the property, and otherwise in all
+// names we know of
+fn enum_value(property: &str, name: &str) -> Option<u32> {
+    let find = |names| match names {
+        EnumNames::Index(index) => lookup(index, name),
+        EnumNames::Names(names) => lookup_name(names, name),
+        EnumNames::Table(names) => names
+            .iter()
+            .position(|n| !n.is_empty() && *n == name)
+            .map(|i| i as u32),
+    };
+    if property == "property-list" {
+        return property_id(name);
+    }
+    if let Some(value) = enum_names(property).and_then(find) {
+        return Some(value);
+    }
+    [
+        "units",
+        "object-type",
+        "segmentation-supported",
+        "present-value",
+        "event-state",
+        "reliability",
+        "system-status",
+        "polarity",
+        "node-type",
+        "life-safety-state",
+    ]
+    .iter()
+    .filter_map(|property| enum_names(property))
+    .find_map(find)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epics(objects: Vec<(ObjectId, Vec<(ObjectPropertyId, BACnetValue)>)>) -> Epics {
+        let objects = objects
+            .into_iter()
+            .map(|(id, properties)| {
+                let mut properties = properties
+                    .into_iter()
+                    .map(|(property, value)| (property, EpicsProperty::new(property, value)))
+                    .collect::<BTreeMap<_, _>>();
+                let object_id = BACnetValue::ObjectId {
+                    object_type: id.object_type,
+                    object_instance: id.object_instance,
+                };
+                properties.insert(
+                    bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER,
+                    EpicsProperty::new(
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER,
+                        object_id,
+                    ),
+                );
+                (id, EpicsObject { id, properties })
+            })
+            .collect();
+        Epics {
+            device_id: ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE, 1234),
+            objects,
+        }
+    }
+
+    fn round_trip(epics: &Epics) -> Epics {
+        epics.text().show_values(true).to_string().parse().unwrap()
+    }
+
+    fn device() -> (ObjectId, Vec<(ObjectPropertyId, BACnetValue)>) {
+        (
+            ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE, 1234),
+            vec![(
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME,
+                BACnetValue::String("Gateway".to_string()),
+            )],
+        )
+    }
+
+    #[test]
+    fn round_trip_keeps_values() {
+        let epics = epics(vec![
+            device(),
+            (
+                ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_VALUE, 1),
+                vec![
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+                        BACnetValue::Real(21.5),
+                    ),
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_OUT_OF_SERVICE,
+                        BACnetValue::Bool(false),
+                    ),
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_STATUS_FLAGS,
+                        BACnetValue::BitString(vec![false, true, false, false]),
+                    ),
+                ],
+            ),
+        ]);
+        let parsed = round_trip(&epics);
+        assert_eq!(parsed.device_id, epics.device_id);
+        assert_eq!(parsed.objects, epics.objects);
+    }
+
+    #[test]
+    fn round_trip_keeps_proprietary_properties() {
+        let id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_INPUT, 3);
+        let epics = epics(vec![
+            device(),
+            (
+                id,
+                vec![(512, BACnetValue::Uint(7)), (600, BACnetValue::Real(1.5))],
+            ),
+        ]);
+        let parsed = round_trip(&epics);
+        let object = parsed.object(id).unwrap();
+        assert_eq!(object.property(512), Some(&BACnetValue::Uint(7)));
+        assert_eq!(
+            object.property_by_name("proprietary-600"),
+            Some(&BACnetValue::Real(1.5))
+        );
+    }
+
+    #[test]
+    fn numbers_are_enumerated_only_on_binary_objects() {
+        let multi_state = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_VALUE, 1);
+        let binary = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_BINARY_VALUE, 1);
+        let epics = epics(vec![
+            device(),
+            (
+                multi_state,
+                vec![
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+                        BACnetValue::Uint(3),
+                    ),
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_RELINQUISH_DEFAULT,
+                        BACnetValue::Uint(1),
+                    ),
+                ],
+            ),
+            (
+                binary,
+                vec![
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE,
+                        BACnetValue::Enum(1, None),
+                    ),
+                    (
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_RELINQUISH_DEFAULT,
+                        BACnetValue::Enum(0, Some("inactive".to_string())),
+                    ),
+                ],
+            ),
+        ]);
+        let parsed = round_trip(&epics);
+        assert_eq!(parsed.object(multi_state), epics.object(multi_state));
+        assert_eq!(parsed.object(binary), epics.object(binary));
+    }
+
+    #[test]
+    fn units_are_enumerated() {
+        let id = ObjectId::new(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_ANALOG_INPUT, 1);
+        let epics = epics(vec![
+            device(),
+            (
+                id,
+                vec![(
+                    bacnet_sys::BACNET_PROPERTY_ID_PROP_UNITS,
+                    BACnetValue::Enum(62, None),
+                )],
+            ),
+        ]);
+        let parsed = round_trip(&epics);
+        assert_eq!(
+            parsed
+                .object(id)
+                .unwrap()
+                .property(bacnet_sys::BACNET_PROPERTY_ID_PROP_UNITS),
+            Some(&BACnetValue::Enum(62, None))
+        );
+    }
+
+    #[test]
+    fn dates_wildcard_times_and_named_enumerations() {
+        let text = r#"
+List of Objects in Test Device:
+{
+  {
+    object-identifier: (device, 1234)
+    object-name: "Gateway"
+    local-date: Monday, January 19, 2026
+    local-time: **:**:**.**
+    apdu-timeout: 3000 W
+    vendor-thing: some unknown-value
+  },
+  {
+    object-identifier: (event-enrollment, 1)
+    notify-type: alarm
+    event-type: change-of-value
+    event-state: normal
+  }
+}
+"#;
+        let epics: Epics = text.parse().unwrap();
+        let device = epics.device().unwrap();
+        assert_eq!(
+            device.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_LOCAL_DATE),
+            Some(&BACnetValue::String("Monday, January 19, 2026".to_string()))
+        );
+        assert_eq!(
+            device.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_LOCAL_TIME),
+            Some(&BACnetValue::String("**:**:**.**".to_string()))
+        );
+        assert_eq!(
+            device.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_APDU_TIMEOUT),
+            Some(&BACnetValue::Uint(3000))
+        );
+
+        let enrollment = epics
+            .object(ObjectId::new(
+                bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_EVENT_ENROLLMENT,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(
+            enrollment.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_NOTIFY_TYPE),
+            Some(&BACnetValue::Enum(0, Some("alarm".to_string())))
+        );
+        assert_eq!(
+            enrollment.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_EVENT_TYPE),
+            Some(&BACnetValue::Enum(2, Some("change-of-value".to_string())))
+        );
+        assert_eq!(
+            enrollment.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_EVENT_STATE),
+            Some(&BACnetValue::Enum(0, Some("normal".to_string())))
+        );
+    }
+
+    #[test]
+    fn unknown_enumerations_are_kept_as_text() {
+        let text = r#"
+List of Objects in Test Device:
+{
+  {
+    object-identifier: (device, 1234)
+    system-status: warming-up
+    description: "Gateway"
+  }
+}
+"#;
+        let epics: Epics = text.parse().unwrap();
+        let device = epics.device().unwrap();
+        assert_eq!(
+            device.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_SYSTEM_STATUS),
+            Some(&BACnetValue::String("warming-up".to_string()))
+        );
+        assert_eq!(
+            device.property(bacnet_sys::BACNET_PROPERTY_ID_PROP_DESCRIPTION),
+            Some(&BACnetValue::String("Gateway".to_string()))
+        );
+    }
+}
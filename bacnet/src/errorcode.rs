@@ -0,0 +1,235 @@
+//! Error classes and codes, and reject and abort reasons reported by devices
+
+use std::fmt;
+
+// Define an enum for one of the stack's C enums. Values that aren't listed (e.g. proprietary ones)
+// are kept as `Unknown`. The text is the one from the stack's bactext.
+macro_rules! bacnet_enum {
+    ($(#[$meta:meta])* $name:ident, $text:ident { $($variant:ident = $value:ident,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+            /// A proprietary or otherwise unknown value
+            Unknown(u32),
+        }
+
+        impl $name {
+            pub fn from_u32(value: u32) -> Self {
+                match value {
+                    $(bacnet_sys::$value => $name::$variant,)*
+                    value => $name::Unknown(value),
+                }
+            }
+
+            pub fn to_u32(self) -> u32 {
+                match self {
+                    $($name::$variant => bacnet_sys::$value,)*
+                    $name::Unknown(value) => value,
+                }
+            }
+
+            /// The name of the value, as used by the BACnet stack
+            pub fn name(self) -> String {
+                crate::cstr(unsafe { bacnet_sys::$text(self.to_u32()) })
+            }
+        }
+
+        impl From<u32> for $name {
+            fn from(value: u32) -> Self {
+                $name::from_u32(value)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} ({})", self.name(), self.to_u32())
+            }
+        }
+    };
+}
+
+bacnet_enum! {
+    /// The class of an Error returned by a device
+    ErrorClass, bactext_error_class_name {
+        Device = BACNET_ERROR_CLASS_ERROR_CLASS_DEVICE,
+        Object = BACNET_ERROR_CLASS_ERROR_CLASS_OBJECT,
+        Property = BACNET_ERROR_CLASS_ERROR_CLASS_PROPERTY,
+        Resources = BACNET_ERROR_CLASS_ERROR_CLASS_RESOURCES,
+        Security = BACNET_ERROR_CLASS_ERROR_CLASS_SECURITY,
+        Services = BACNET_ERROR_CLASS_ERROR_CLASS_SERVICES,
+        Vt = BACNET_ERROR_CLASS_ERROR_CLASS_VT,
+        Communication = BACNET_ERROR_CLASS_ERROR_CLASS_COMMUNICATION,
+    }
+}
+
+bacnet_enum! {
+    /// The code of an Error returned by a device
+    ErrorCode, bactext_error_code_name {
+        Other = BACNET_ERROR_CODE_ERROR_CODE_OTHER,
+        DeviceBusy = BACNET_ERROR_CODE_ERROR_CODE_DEVICE_BUSY,
+        ConfigurationInProgress = BACNET_ERROR_CODE_ERROR_CODE_CONFIGURATION_IN_PROGRESS,
+        OperationalProblem = BACNET_ERROR_CODE_ERROR_CODE_OPERATIONAL_PROBLEM,
+        DynamicCreationNotSupported = BACNET_ERROR_CODE_ERROR_CODE_DYNAMIC_CREATION_NOT_SUPPORTED,
+        NoObjectsOfSpecifiedType = BACNET_ERROR_CODE_ERROR_CODE_NO_OBJECTS_OF_SPECIFIED_TYPE,
+        ObjectDeletionNotPermitted = BACNET_ERROR_CODE_ERROR_CODE_OBJECT_DELETION_NOT_PERMITTED,
+        ObjectIdentifierAlreadyExists = BACNET_ERROR_CODE_ERROR_CODE_OBJECT_IDENTIFIER_ALREADY_EXISTS,
+        ReadAccessDenied = BACNET_ERROR_CODE_ERROR_CODE_READ_ACCESS_DENIED,
+        UnknownObject = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_OBJECT,
+        UnsupportedObjectType = BACNET_ERROR_CODE_ERROR_CODE_UNSUPPORTED_OBJECT_TYPE,
+        CharacterSetNotSupported = BACNET_ERROR_CODE_ERROR_CODE_CHARACTER_SET_NOT_SUPPORTED,
+        DatatypeNotSupported = BACNET_ERROR_CODE_ERROR_CODE_DATATYPE_NOT_SUPPORTED,
+        InconsistentSelectionCriterion = BACNET_ERROR_CODE_ERROR_CODE_INCONSISTENT_SELECTION_CRITERION,
+        InvalidArrayIndex = BACNET_ERROR_CODE_ERROR_CODE_INVALID_ARRAY_INDEX,
+        InvalidDataType = BACNET_ERROR_CODE_ERROR_CODE_INVALID_DATA_TYPE,
+        NotCovProperty = BACNET_ERROR_CODE_ERROR_CODE_NOT_COV_PROPERTY,
+        OptionalFunctionalityNotSupported = BACNET_ERROR_CODE_ERROR_CODE_OPTIONAL_FUNCTIONALITY_NOT_SUPPORTED,
+        PropertyIsNotAnArray = BACNET_ERROR_CODE_ERROR_CODE_PROPERTY_IS_NOT_AN_ARRAY,
+        UnknownProperty = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_PROPERTY,
+        ValueOutOfRange = BACNET_ERROR_CODE_ERROR_CODE_VALUE_OUT_OF_RANGE,
+        WriteAccessDenied = BACNET_ERROR_CODE_ERROR_CODE_WRITE_ACCESS_DENIED,
+        NoSpaceForObject = BACNET_ERROR_CODE_ERROR_CODE_NO_SPACE_FOR_OBJECT,
+        NoSpaceToAddListElement = BACNET_ERROR_CODE_ERROR_CODE_NO_SPACE_TO_ADD_LIST_ELEMENT,
+        NoSpaceToWriteProperty = BACNET_ERROR_CODE_ERROR_CODE_NO_SPACE_TO_WRITE_PROPERTY,
+        AuthenticationFailed = BACNET_ERROR_CODE_ERROR_CODE_AUTHENTICATION_FAILED,
+        IncompatibleSecurityLevels = BACNET_ERROR_CODE_ERROR_CODE_INCOMPATIBLE_SECURITY_LEVELS,
+        InvalidOperatorName = BACNET_ERROR_CODE_ERROR_CODE_INVALID_OPERATOR_NAME,
+        KeyGenerationError = BACNET_ERROR_CODE_ERROR_CODE_KEY_GENERATION_ERROR,
+        PasswordFailure = BACNET_ERROR_CODE_ERROR_CODE_PASSWORD_FAILURE,
+        SecurityNotSupported = BACNET_ERROR_CODE_ERROR_CODE_SECURITY_NOT_SUPPORTED,
+        Timeout = BACNET_ERROR_CODE_ERROR_CODE_TIMEOUT,
+        CovSubscriptionFailed = BACNET_ERROR_CODE_ERROR_CODE_COV_SUBSCRIPTION_FAILED,
+        DuplicateName = BACNET_ERROR_CODE_ERROR_CODE_DUPLICATE_NAME,
+        DuplicateObjectId = BACNET_ERROR_CODE_ERROR_CODE_DUPLICATE_OBJECT_ID,
+        FileAccessDenied = BACNET_ERROR_CODE_ERROR_CODE_FILE_ACCESS_DENIED,
+        InconsistentParameters = BACNET_ERROR_CODE_ERROR_CODE_INCONSISTENT_PARAMETERS,
+        InvalidConfigurationData = BACNET_ERROR_CODE_ERROR_CODE_INVALID_CONFIGURATION_DATA,
+        InvalidFileAccessMethod = BACNET_ERROR_CODE_ERROR_CODE_INVALID_FILE_ACCESS_METHOD,
+        InvalidFileStartPosition = BACNET_ERROR_CODE_ERROR_CODE_INVALID_FILE_START_POSITION,
+        InvalidParameterDataType = BACNET_ERROR_CODE_ERROR_CODE_INVALID_PARAMETER_DATA_TYPE,
+        InvalidTimeStamp = BACNET_ERROR_CODE_ERROR_CODE_INVALID_TIME_STAMP,
+        MissingRequiredParameter = BACNET_ERROR_CODE_ERROR_CODE_MISSING_REQUIRED_PARAMETER,
+        PropertyIsNotAList = BACNET_ERROR_CODE_ERROR_CODE_PROPERTY_IS_NOT_A_LIST,
+        ServiceRequestDenied = BACNET_ERROR_CODE_ERROR_CODE_SERVICE_REQUEST_DENIED,
+        UnknownVtClass = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_VT_CLASS,
+        UnknownVtSession = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_VT_SESSION,
+        NoVtSessionsAvailable = BACNET_ERROR_CODE_ERROR_CODE_NO_VT_SESSIONS_AVAILABLE,
+        VtSessionAlreadyClosed = BACNET_ERROR_CODE_ERROR_CODE_VT_SESSION_ALREADY_CLOSED,
+        VtSessionTerminationFailure = BACNET_ERROR_CODE_ERROR_CODE_VT_SESSION_TERMINATION_FAILURE,
+        AbortBufferOverflow = BACNET_ERROR_CODE_ERROR_CODE_ABORT_BUFFER_OVERFLOW,
+        AbortInvalidApduInThisState = BACNET_ERROR_CODE_ERROR_CODE_ABORT_INVALID_APDU_IN_THIS_STATE,
+        AbortPreemptedByHigherPriorityTask = BACNET_ERROR_CODE_ERROR_CODE_ABORT_PREEMPTED_BY_HIGHER_PRIORITY_TASK,
+        AbortSegmentationNotSupported = BACNET_ERROR_CODE_ERROR_CODE_ABORT_SEGMENTATION_NOT_SUPPORTED,
+        AbortProprietary = BACNET_ERROR_CODE_ERROR_CODE_ABORT_PROPRIETARY,
+        AbortOther = BACNET_ERROR_CODE_ERROR_CODE_ABORT_OTHER,
+        InvalidTag = BACNET_ERROR_CODE_ERROR_CODE_INVALID_TAG,
+        NetworkDown = BACNET_ERROR_CODE_ERROR_CODE_NETWORK_DOWN,
+        RejectBufferOverflow = BACNET_ERROR_CODE_ERROR_CODE_REJECT_BUFFER_OVERFLOW,
+        RejectInconsistentParameters = BACNET_ERROR_CODE_ERROR_CODE_REJECT_INCONSISTENT_PARAMETERS,
+        RejectInvalidParameterDataType = BACNET_ERROR_CODE_ERROR_CODE_REJECT_INVALID_PARAMETER_DATA_TYPE,
+        RejectInvalidTag = BACNET_ERROR_CODE_ERROR_CODE_REJECT_INVALID_TAG,
+        RejectMissingRequiredParameter = BACNET_ERROR_CODE_ERROR_CODE_REJECT_MISSING_REQUIRED_PARAMETER,
+        RejectParameterOutOfRange = BACNET_ERROR_CODE_ERROR_CODE_REJECT_PARAMETER_OUT_OF_RANGE,
+        RejectTooManyArguments = BACNET_ERROR_CODE_ERROR_CODE_REJECT_TOO_MANY_ARGUMENTS,
+        RejectUndefinedEnumeration = BACNET_ERROR_CODE_ERROR_CODE_REJECT_UNDEFINED_ENUMERATION,
+        RejectUnrecognizedService = BACNET_ERROR_CODE_ERROR_CODE_REJECT_UNRECOGNIZED_SERVICE,
+        RejectProprietary = BACNET_ERROR_CODE_ERROR_CODE_REJECT_PROPRIETARY,
+        RejectOther = BACNET_ERROR_CODE_ERROR_CODE_REJECT_OTHER,
+        UnknownDevice = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_DEVICE,
+        UnknownRoute = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_ROUTE,
+        ValueNotInitialized = BACNET_ERROR_CODE_ERROR_CODE_VALUE_NOT_INITIALIZED,
+        InvalidEventState = BACNET_ERROR_CODE_ERROR_CODE_INVALID_EVENT_STATE,
+        NoAlarmConfigured = BACNET_ERROR_CODE_ERROR_CODE_NO_ALARM_CONFIGURED,
+        LogBufferFull = BACNET_ERROR_CODE_ERROR_CODE_LOG_BUFFER_FULL,
+        LoggedValuePurged = BACNET_ERROR_CODE_ERROR_CODE_LOGGED_VALUE_PURGED,
+        NoPropertySpecified = BACNET_ERROR_CODE_ERROR_CODE_NO_PROPERTY_SPECIFIED,
+        NotConfiguredForTriggeredLogging = BACNET_ERROR_CODE_ERROR_CODE_NOT_CONFIGURED_FOR_TRIGGERED_LOGGING,
+        UnknownSubscription = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_SUBSCRIPTION,
+        ParameterOutOfRange = BACNET_ERROR_CODE_ERROR_CODE_PARAMETER_OUT_OF_RANGE,
+        ListElementNotFound = BACNET_ERROR_CODE_ERROR_CODE_LIST_ELEMENT_NOT_FOUND,
+        Busy = BACNET_ERROR_CODE_ERROR_CODE_BUSY,
+        CommunicationDisabled = BACNET_ERROR_CODE_ERROR_CODE_COMMUNICATION_DISABLED,
+        Success = BACNET_ERROR_CODE_ERROR_CODE_SUCCESS,
+        AccessDenied = BACNET_ERROR_CODE_ERROR_CODE_ACCESS_DENIED,
+        BadDestinationAddress = BACNET_ERROR_CODE_ERROR_CODE_BAD_DESTINATION_ADDRESS,
+        BadDestinationDeviceId = BACNET_ERROR_CODE_ERROR_CODE_BAD_DESTINATION_DEVICE_ID,
+        BadSignature = BACNET_ERROR_CODE_ERROR_CODE_BAD_SIGNATURE,
+        BadSourceAddress = BACNET_ERROR_CODE_ERROR_CODE_BAD_SOURCE_ADDRESS,
+        BadTimestamp = BACNET_ERROR_CODE_ERROR_CODE_BAD_TIMESTAMP,
+        CannotUseKey = BACNET_ERROR_CODE_ERROR_CODE_CANNOT_USE_KEY,
+        CannotVerifyMessageId = BACNET_ERROR_CODE_ERROR_CODE_CANNOT_VERIFY_MESSAGE_ID,
+        CorrectKeyRevision = BACNET_ERROR_CODE_ERROR_CODE_CORRECT_KEY_REVISION,
+        DestinationDeviceIdRequired = BACNET_ERROR_CODE_ERROR_CODE_DESTINATION_DEVICE_ID_REQUIRED,
+        DuplicateMessage = BACNET_ERROR_CODE_ERROR_CODE_DUPLICATE_MESSAGE,
+        EncryptionNotConfigured = BACNET_ERROR_CODE_ERROR_CODE_ENCRYPTION_NOT_CONFIGURED,
+        EncryptionRequired = BACNET_ERROR_CODE_ERROR_CODE_ENCRYPTION_REQUIRED,
+        IncorrectKey = BACNET_ERROR_CODE_ERROR_CODE_INCORRECT_KEY,
+        InvalidKeyData = BACNET_ERROR_CODE_ERROR_CODE_INVALID_KEY_DATA,
+        KeyUpdateInProgress = BACNET_ERROR_CODE_ERROR_CODE_KEY_UPDATE_IN_PROGRESS,
+        MalformedMessage = BACNET_ERROR_CODE_ERROR_CODE_MALFORMED_MESSAGE,
+        NotKeyServer = BACNET_ERROR_CODE_ERROR_CODE_NOT_KEY_SERVER,
+        SecurityNotConfigured = BACNET_ERROR_CODE_ERROR_CODE_SECURITY_NOT_CONFIGURED,
+        SourceSecurityRequired = BACNET_ERROR_CODE_ERROR_CODE_SOURCE_SECURITY_REQUIRED,
+        TooManyKeys = BACNET_ERROR_CODE_ERROR_CODE_TOO_MANY_KEYS,
+        UnknownAuthenticationType = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_AUTHENTICATION_TYPE,
+        UnknownKey = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_KEY,
+        UnknownKeyRevision = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_KEY_REVISION,
+        UnknownSourceMessage = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_SOURCE_MESSAGE,
+        NotRouterToDnet = BACNET_ERROR_CODE_ERROR_CODE_NOT_ROUTER_TO_DNET,
+        RouterBusy = BACNET_ERROR_CODE_ERROR_CODE_ROUTER_BUSY,
+        UnknownNetworkMessage = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_NETWORK_MESSAGE,
+        MessageTooLong = BACNET_ERROR_CODE_ERROR_CODE_MESSAGE_TOO_LONG,
+        SecurityError = BACNET_ERROR_CODE_ERROR_CODE_SECURITY_ERROR,
+        AddressingError = BACNET_ERROR_CODE_ERROR_CODE_ADDRESSING_ERROR,
+        WriteBdtFailed = BACNET_ERROR_CODE_ERROR_CODE_WRITE_BDT_FAILED,
+        ReadBdtFailed = BACNET_ERROR_CODE_ERROR_CODE_READ_BDT_FAILED,
+        RegisterForeignDeviceFailed = BACNET_ERROR_CODE_ERROR_CODE_REGISTER_FOREIGN_DEVICE_FAILED,
+        ReadFdtFailed = BACNET_ERROR_CODE_ERROR_CODE_READ_FDT_FAILED,
+        DeleteFdtEntryFailed = BACNET_ERROR_CODE_ERROR_CODE_DELETE_FDT_ENTRY_FAILED,
+        DistributeBroadcastFailed = BACNET_ERROR_CODE_ERROR_CODE_DISTRIBUTE_BROADCAST_FAILED,
+        UnknownFileSize = BACNET_ERROR_CODE_ERROR_CODE_UNKNOWN_FILE_SIZE,
+        AbortApduTooLong = BACNET_ERROR_CODE_ERROR_CODE_ABORT_APDU_TOO_LONG,
+        AbortApplicationExceededReplyTime = BACNET_ERROR_CODE_ERROR_CODE_ABORT_APPLICATION_EXCEEDED_REPLY_TIME,
+        AbortOutOfResources = BACNET_ERROR_CODE_ERROR_CODE_ABORT_OUT_OF_RESOURCES,
+        AbortTsmTimeout = BACNET_ERROR_CODE_ERROR_CODE_ABORT_TSM_TIMEOUT,
+        AbortWindowSizeOutOfRange = BACNET_ERROR_CODE_ERROR_CODE_ABORT_WINDOW_SIZE_OUT_OF_RANGE,
+        FileFull = BACNET_ERROR_CODE_ERROR_CODE_FILE_FULL,
+        InconsistentConfiguration = BACNET_ERROR_CODE_ERROR_CODE_INCONSISTENT_CONFIGURATION,
+        InconsistentObjectType = BACNET_ERROR_CODE_ERROR_CODE_INCONSISTENT_OBJECT_TYPE,
+        InternalError = BACNET_ERROR_CODE_ERROR_CODE_INTERNAL_ERROR,
+        NotConfigured = BACNET_ERROR_CODE_ERROR_CODE_NOT_CONFIGURED,
+        OutOfMemory = BACNET_ERROR_CODE_ERROR_CODE_OUT_OF_MEMORY,
+        ValueTooLong = BACNET_ERROR_CODE_ERROR_CODE_VALUE_TOO_LONG,
+        AbortInsufficientSecurity = BACNET_ERROR_CODE_ERROR_CODE_ABORT_INSUFFICIENT_SECURITY,
+        AbortSecurityError = BACNET_ERROR_CODE_ERROR_CODE_ABORT_SECURITY_ERROR,
+    }
+}
+
+bacnet_enum! {
+    /// The reason a device rejected a request
+    RejectReason, bactext_reject_reason_name {
+        Other = BACNET_REJECT_REASON_REJECT_REASON_OTHER,
+        BufferOverflow = BACNET_REJECT_REASON_REJECT_REASON_BUFFER_OVERFLOW,
+        InconsistentParameters = BACNET_REJECT_REASON_REJECT_REASON_INCONSISTENT_PARAMETERS,
+        InvalidParameterDataType = BACNET_REJECT_REASON_REJECT_REASON_INVALID_PARAMETER_DATA_TYPE,
+        InvalidTag = BACNET_REJECT_REASON_REJECT_REASON_INVALID_TAG,
+        MissingRequiredParameter = BACNET_REJECT_REASON_REJECT_REASON_MISSING_REQUIRED_PARAMETER,
+        ParameterOutOfRange = BACNET_REJECT_REASON_REJECT_REASON_PARAMETER_OUT_OF_RANGE,
+        TooManyArguments = BACNET_REJECT_REASON_REJECT_REASON_TOO_MANY_ARGUMENTS,
+        UndefinedEnumeration = BACNET_REJECT_REASON_REJECT_REASON_UNDEFINED_ENUMERATION,
+        UnrecognizedService = BACNET_REJECT_REASON_REJECT_REASON_UNRECOGNIZED_SERVICE,
+    }
+}
+
+bacnet_enum! {
+    /// The reason a device aborted a request
+    AbortReason, bactext_abort_reason_name {
+        Other = BACNET_ABORT_REASON_ABORT_REASON_OTHER,
+        BufferOverflow = BACNET_ABORT_REASON_ABORT_REASON_BUFFER_OVERFLOW,
+        InvalidApduInThisState = BACNET_ABORT_REASON_ABORT_REASON_INVALID_APDU_IN_THIS_STATE,
+        PreemptedByHigherPriorityTask = BACNET_ABORT_REASON_ABORT_REASON_PREEMPTED_BY_HIGHER_PRIORITY_TASK,
+        SegmentationNotSupported = BACNET_ABORT_REASON_ABORT_REASON_SEGMENTATION_NOT_SUPPORTED,
+        SecurityError = BACNET_ABORT_REASON_ABORT_REASON_SECURITY_ERROR,
+        InsufficientSecurity = BACNET_ABORT_REASON_ABORT_REASON_INSUFFICIENT_SECURITY,
+    }
+}
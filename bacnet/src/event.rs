@@ -0,0 +1,400 @@
+//! Receiving ConfirmedEventNotification and UnconfirmedEventNotification
+//!
+//! Incoming notifications are reported on the channel returned by `subscribe()`. Confirmed
+//! notifications are acknowledged with a SimpleACK as soon as they've been decoded. Like
+//! everything else in the stack they are only processed while the stack is driven forward, e.g.
+//! by `bacnet::poll()` or an on-going request.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::alarm::{EventState, EventTimestamp, NotifyType};
+
+lazy_static! {
+    /// Where to report incoming event notifications. Set by `subscribe()`.
+    static ref EVENT_SUBSCRIBER: Mutex<Option<Sender<EventNotification>>> = Mutex::new(None);
+}
+
+/// An event notification received from the network
+#[derive(Debug, Clone)]
+pub struct EventNotification {
+    /// `true` for ConfirmedEventNotification, `false` for UnconfirmedEventNotification
+    pub confirmed: bool,
+    pub process_identifier: u32,
+    /// Instance of the device that sent the notification
+    pub initiating_device: u32,
+    pub object_type: u32,
+    pub object_instance: u32,
+    pub timestamp: Option<EventTimestamp>,
+    pub notification_class: u32,
+    pub priority: u8,
+    /// One of the `BACNET_EVENT_TYPE_*` values
+    pub event_type: u32,
+    pub message_text: Option<String>,
+    pub notify_type: NotifyType,
+    pub ack_required: bool,
+    pub from_state: EventState,
+    pub to_state: EventState,
+    /// `None` for ack-notifications, and for event types the stack can't decode
+    pub event_values: Option<EventValues>,
+    pub mac_addr: [u8; 6],
+    pub network_number: u16,
+    pub addr: [u8; 6],
+}
+
+/// The status-flags of the event object
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatusFlags {
+    pub in_alarm: bool,
+    pub fault: bool,
+    pub overridden: bool,
+    pub out_of_service: bool,
+}
+
+/// A BACnetPropertyStates value from a change-of-state event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyState {
+    Boolean(bool),
+    Unsigned(u64),
+    /// An enumerated state. `kind` is one of the `BACNET_PROPERTY_STATE_TYPE_*` values.
+    Enumerated {
+        kind: u32,
+        value: u32,
+    },
+}
+
+/// The event values of a notification, depending on the event type
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventValues {
+    ChangeOfBitstring {
+        referenced_bitstring: Vec<bool>,
+        status_flags: StatusFlags,
+    },
+    ChangeOfState {
+        new_state: PropertyState,
+        status_flags: StatusFlags,
+    },
+    ChangeOfValue {
+        new_value: ChangedValue,
+        status_flags: StatusFlags,
+    },
+    FloatingLimit {
+        reference_value: f32,
+        status_flags: StatusFlags,
+        setpoint_value: f32,
+        error_limit: f32,
+    },
+    OutOfRange {
+        exceeding_value: f32,
+        status_flags: StatusFlags,
+        deadband: f32,
+        exceeded_limit: f32,
+    },
+    ChangeOfLifeSafety {
+        new_state: u32,
+        new_mode: u32,
+        status_flags: StatusFlags,
+        operation_expected: u32,
+    },
+    BufferReady {
+        /// Device, object type, object instance and property of the buffer
+        buffer_device: u32,
+        buffer_object_type: u32,
+        buffer_object_instance: u32,
+        buffer_property: u32,
+        previous_notification: u32,
+        current_notification: u32,
+    },
+    UnsignedRange {
+        exceeding_value: u32,
+        status_flags: StatusFlags,
+        exceeded_limit: u32,
+    },
+}
+
+/// The new value of a change-of-value event
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangedValue {
+    Bits(Vec<bool>),
+    Real(f32),
+}
+
+/// Start receiving incoming event notifications on the returned channel.
+///
+/// Only the most recent subscriber receives notifications.
+pub fn subscribe() -> Receiver<EventNotification> {
+    crate::init_stack();
+    let (tx, rx) = channel();
+    if let Ok(mut lock) = EVENT_SUBSCRIBER.lock() {
+        *lock = Some(tx);
+    }
+    rx
+}
+
+fn bits(bit_string: &bacnet_sys::BACNET_BIT_STRING) -> Vec<bool> {
+    let mut bit_string = *bit_string;
+    let nbits = unsafe { bacnet_sys::bitstring_bits_used(&mut bit_string) };
+    (0..nbits)
+        .map(|i| unsafe { bacnet_sys::bitstring_bit(&mut bit_string, i) })
+        .collect()
+}
+
+impl StatusFlags {
+    fn from_sys(bit_string: &bacnet_sys::BACNET_BIT_STRING) -> Self {
+        let bits = bits(bit_string);
+        let bit = |n| bits.get(n).copied().unwrap_or(false);
+        StatusFlags {
+            in_alarm: bit(0),
+            fault: bit(1),
+            overridden: bit(2),
+            out_of_service: bit(3),
+        }
+    }
+}
+
+impl PropertyState {
+    fn from_sys(state: &bacnet_sys::BACNET_PROPERTY_STATE) -> Self {
+        match state.tag {
+            bacnet_sys::BACNET_PROPERTY_STATE_TYPE_BOOLEAN_VALUE => {
+                PropertyState::Boolean(unsafe { state.state.booleanValue })
+            }
+            bacnet_sys::BACNET_PROPERTY_STATE_TYPE_UNSIGNED_VALUE => {
+                PropertyState::Unsigned(unsafe { state.state.unsignedValue })
+            }
+            // All the other states are enumerations
+            kind => PropertyState::Enumerated {
+                kind,
+                value: unsafe { state.state.state },
+            },
+        }
+    }
+}
+
+impl EventValues {
+    fn from_sys(data: &bacnet_sys::BACNET_EVENT_NOTIFICATION_DATA) -> Option<Self> {
+        if data.notifyType == bacnet_sys::BACNET_NOTIFY_TYPE_NOTIFY_ACK_NOTIFICATION {
+            return None;
+        }
+        let params = &data.notificationParams;
+        let values = unsafe {
+            match data.eventType {
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_CHANGE_OF_BITSTRING => {
+                    let p = &params.changeOfBitstring;
+                    EventValues::ChangeOfBitstring {
+                        referenced_bitstring: bits(&p.referencedBitString),
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_CHANGE_OF_STATE => {
+                    let p = &params.changeOfState;
+                    EventValues::ChangeOfState {
+                        new_state: PropertyState::from_sys(&p.newState),
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_CHANGE_OF_VALUE => {
+                    let p = &params.changeOfValue;
+                    let new_value =
+                        if p.tag == bacnet_sys::CHANGE_OF_VALUE_TYPE_CHANGE_OF_VALUE_BITS {
+                            ChangedValue::Bits(bits(&p.newValue.changedBits))
+                        } else {
+                            ChangedValue::Real(p.newValue.changeValue)
+                        };
+                    EventValues::ChangeOfValue {
+                        new_value,
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_FLOATING_LIMIT => {
+                    let p = &params.floatingLimit;
+                    EventValues::FloatingLimit {
+                        reference_value: p.referenceValue,
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                        setpoint_value: p.setPointValue,
+                        error_limit: p.errorLimit,
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_OUT_OF_RANGE => {
+                    let p = &params.outOfRange;
+                    EventValues::OutOfRange {
+                        exceeding_value: p.exceedingValue,
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                        deadband: p.deadband,
+                        exceeded_limit: p.exceededLimit,
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_CHANGE_OF_LIFE_SAFETY => {
+                    let p = &params.changeOfLifeSafety;
+                    EventValues::ChangeOfLifeSafety {
+                        new_state: p.newState,
+                        new_mode: p.newMode,
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                        operation_expected: p.operationExpected,
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_BUFFER_READY => {
+                    let p = &params.bufferReady;
+                    EventValues::BufferReady {
+                        buffer_device: p.bufferProperty.deviceIdentifier.instance,
+                        buffer_object_type: p.bufferProperty.objectIdentifier.type_,
+                        buffer_object_instance: p.bufferProperty.objectIdentifier.instance,
+                        buffer_property: p.bufferProperty.propertyIdentifier,
+                        previous_notification: p.previousNotification,
+                        current_notification: p.currentNotification,
+                    }
+                }
+                bacnet_sys::BACNET_EVENT_TYPE_EVENT_UNSIGNED_RANGE => {
+                    let p = &params.unsignedRange;
+                    EventValues::UnsignedRange {
+                        exceeding_value: p.exceedingValue,
+                        status_flags: StatusFlags::from_sys(&p.statusFlags),
+                        exceeded_limit: p.exceededLimit,
+                    }
+                }
+                _ => return None,
+            }
+        };
+        Some(values)
+    }
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn confirmed_event_notification_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_DATA,
+) {
+    crate::catch_panic("confirmed_event_notification_handler", || {
+        let mut my_address = bacnet_sys::BACNET_ADDRESS::default();
+        let mut npdu_data = bacnet_sys::BACNET_NPDU_DATA::default();
+        let mut buf = [0u8; bacnet_sys::MAX_PDU as usize];
+        let (invoke_id, segmented) =
+            unsafe { ((*service_data).invoke_id, (*service_data).segmented_message) };
+
+        let notification = if segmented {
+            None
+        } else {
+            decode_notification(service_request, service_len, src, true)
+        };
+
+        unsafe {
+            bacnet_sys::bip_get_my_address(&mut my_address);
+            bacnet_sys::npdu_encode_npdu_data(
+                &mut npdu_data,
+                false,
+                bacnet_sys::BACNET_MESSAGE_PRIORITY_MESSAGE_PRIORITY_NORMAL,
+            );
+            let npdu_len =
+                bacnet_sys::npdu_encode_pdu(buf.as_mut_ptr(), src, &mut my_address, &mut npdu_data);
+            let apdu = buf[npdu_len as usize..].as_mut_ptr();
+            let len = if notification.is_some() {
+                bacnet_sys::encode_simple_ack(
+                    apdu,
+                    invoke_id,
+                    bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_EVENT_NOTIFICATION as u8,
+                )
+            } else if segmented {
+                bacnet_sys::abort_encode_apdu(
+                    apdu,
+                    invoke_id,
+                    bacnet_sys::BACNET_ABORT_REASON_ABORT_REASON_SEGMENTATION_NOT_SUPPORTED as u8,
+                    true,
+                )
+            } else {
+                bacnet_sys::reject_encode_apdu(apdu, invoke_id, crate::reject_reason(service_len))
+            };
+            bacnet_sys::bip_send_pdu(
+                src,
+                &mut npdu_data,
+                buf.as_mut_ptr(),
+                (npdu_len + len) as u32,
+            );
+        }
+
+        if let Some(notification) = notification {
+            publish(notification);
+        }
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn unconfirmed_event_notification_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+) {
+    crate::catch_panic("unconfirmed_event_notification_handler", || {
+        if let Some(notification) = decode_notification(service_request, service_len, src, false) {
+            publish(notification);
+        }
+    });
+}
+
+fn decode_notification(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    confirmed: bool,
+) -> Option<EventNotification> {
+    let mut data = bacnet_sys::BACNET_EVENT_NOTIFICATION_DATA::default();
+    let mut message_text = bacnet_sys::BACNET_CHARACTER_STRING::default();
+    data.messageText = &mut message_text;
+
+    let len = unsafe {
+        bacnet_sys::event_notify_decode_service_request(
+            service_request,
+            service_len.into(),
+            &mut data,
+        )
+    };
+    if len <= 0 {
+        error!("unable to decode event notification...");
+        return None;
+    }
+
+    // The decoder leaves an empty string when there's no message text
+    let message_text = if message_text.length > 0 {
+        let text = &message_text.value[..message_text.length];
+        let text: Vec<u8> = text.iter().map(|&c| c as u8).collect();
+        Some(String::from_utf8_lossy(&text).into_owned())
+    } else {
+        None
+    };
+
+    let (mac_addr, network_number, addr) = crate::source_address(src);
+
+    let notification = EventNotification {
+        confirmed,
+        process_identifier: data.processIdentifier,
+        initiating_device: data.initiatingObjectIdentifier.instance,
+        object_type: data.eventObjectIdentifier.type_,
+        object_instance: data.eventObjectIdentifier.instance,
+        timestamp: EventTimestamp::from_sys(&data.timeStamp),
+        notification_class: data.notificationClass,
+        priority: data.priority,
+        event_type: data.eventType,
+        message_text,
+        notify_type: NotifyType::from_sys(data.notifyType),
+        ack_required: data.ackRequired,
+        from_state: EventState::from_sys(data.fromState),
+        to_state: EventState::from_sys(data.toState),
+        event_values: EventValues::from_sys(&data),
+        mac_addr,
+        network_number,
+        addr,
+    };
+    debug!("event notification {:?}", notification);
+    Some(notification)
+}
+
+fn publish(notification: EventNotification) {
+    if let Ok(mut lock) = EVENT_SUBSCRIBER.lock() {
+        if let Some(tx) = lock.as_ref() {
+            if tx.send(notification).is_err() {
+                // The receiver has gone away
+                *lock = None;
+            }
+        }
+    }
+}
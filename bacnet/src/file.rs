@@ -0,0 +1,249 @@
+//! AtomicReadFile and AtomicWriteFile, for reading and writing File objects
+//!
+//! Files are transferred in chunks that fit within the max APDU of the device, as we don't
+//! support segmentation.
+
+use std::cmp::min;
+
+use crate::{
+    find_matching_device, send_confirmed_request, target_addresses, Ack, BACnetDevice, Error,
+    RequestStatus, Result,
+};
+
+// Room for the APDU header and tags around the file data, as well as the NPDU header when the
+// device is behind a router.
+const FILE_DATA_OVERHEAD: u32 = 40;
+
+// The decoded AtomicReadFile-ACK
+pub(crate) struct ReadFileAck {
+    end_of_file: bool,
+    // The file data, or the contents of the (single) record read
+    data: Vec<u8>,
+    // Number of records returned (record access only)
+    record_count: u64,
+}
+
+impl BACnetDevice {
+    /// Read the entire contents of a File object using stream access
+    pub fn read_file(&self, file_instance: u32) -> Result<Vec<u8>> {
+        let chunk_size = self.file_chunk_size();
+        let mut contents = Vec::new();
+        loop {
+            let start = contents.len() as i32;
+            let ack = self.read_file_ack(|| unsafe {
+                bacnet_sys::Send_Atomic_Read_File_Stream(
+                    self.device_id,
+                    file_instance,
+                    start,
+                    chunk_size as u32,
+                )
+            })?;
+            debug!(
+                "read {} bytes at {} from file {}",
+                ack.data.len(),
+                start,
+                file_instance
+            );
+            contents.extend_from_slice(&ack.data);
+            if ack.end_of_file || ack.data.is_empty() {
+                break;
+            }
+        }
+        Ok(contents)
+    }
+
+    /// Write `data` to a File object using stream access, starting at the beginning of the file
+    ///
+    /// Note that this doesn't truncate the file if it's already larger than `data`.
+    pub fn write_file(&self, file_instance: u32, data: &[u8]) -> Result<()> {
+        let chunk_size = self.file_chunk_size();
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            let start = (i * chunk_size) as i32;
+            let mut file_data = octet_string(chunk);
+            let start = self.write_file_ack(|| unsafe {
+                bacnet_sys::Send_Atomic_Write_File_Stream(
+                    self.device_id,
+                    file_instance,
+                    start,
+                    &mut file_data,
+                )
+            })?;
+            debug!(
+                "wrote {} bytes at {} to file {}",
+                chunk.len(),
+                start,
+                file_instance
+            );
+        }
+        Ok(())
+    }
+
+    /// Read all records of a File object using record access
+    pub fn read_file_records(&self, file_instance: u32) -> Result<Vec<Vec<u8>>> {
+        let mut records = Vec::new();
+        loop {
+            // The stack can only decode a single record per request
+            let start = records.len() as i32;
+            let mut request = bacnet_sys::BACNET_ATOMIC_READ_FILE_DATA {
+                object_type: bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_FILE,
+                object_instance: file_instance,
+                access: bacnet_sys::BACNET_FILE_ACCESS_METHOD_FILE_RECORD_ACCESS,
+                ..Default::default()
+            };
+            request.type_.record.fileStartRecord = start;
+            request.type_.record.RecordCount = 1;
+            let ack = self.read_file_ack(|| {
+                send_confirmed_request(self.device_id, |apdu, invoke_id| unsafe {
+                    bacnet_sys::arf_encode_apdu(apdu.as_mut_ptr(), invoke_id, &mut request)
+                })
+            })?;
+            if ack.record_count > 0 {
+                records.push(ack.data);
+            }
+            if ack.end_of_file || ack.record_count == 0 {
+                break;
+            }
+        }
+        Ok(records)
+    }
+
+    /// Write `records` to a File object using record access, starting at the first record
+    pub fn write_file_records(&self, file_instance: u32, records: &[Vec<u8>]) -> Result<()> {
+        let chunk_size = self.file_chunk_size();
+        for (i, record) in records.iter().enumerate() {
+            if record.len() > chunk_size {
+                return Err(Error::FileRecordTooLarge { len: record.len() });
+            }
+            let mut request = bacnet_sys::BACNET_ATOMIC_WRITE_FILE_DATA {
+                object_type: bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_FILE,
+                object_instance: file_instance,
+                access: bacnet_sys::BACNET_FILE_ACCESS_METHOD_FILE_RECORD_ACCESS,
+                ..Default::default()
+            };
+            request.type_.record.fileStartRecord = i as i32;
+            request.type_.record.returnedRecordCount = 1;
+            request.fileData[0] = octet_string(record);
+            self.write_file_ack(|| {
+                send_confirmed_request(self.device_id, |apdu, invoke_id| unsafe {
+                    bacnet_sys::awf_encode_apdu(apdu.as_mut_ptr(), invoke_id, &mut request)
+                })
+            })?;
+        }
+        Ok(())
+    }
+
+    fn read_file_ack<F>(&self, send: F) -> Result<ReadFileAck>
+    where
+        F: FnOnce() -> u8,
+    {
+        match self.confirmed_request(send)? {
+            Some(Ack::ReadFile(ack)) => Ok(ack),
+            _ => Err(Error::NoValueWasExtracted),
+        }
+    }
+
+    // Returns the start position (or record) reported by the device
+    fn write_file_ack<F>(&self, send: F) -> Result<i32>
+    where
+        F: FnOnce() -> u8,
+    {
+        match self.confirmed_request(send)? {
+            Some(Ack::WriteFile { start }) => Ok(start),
+            _ => Err(Error::NoValueWasExtracted),
+        }
+    }
+
+    // How many bytes of file data to transfer in a single request
+    fn file_chunk_size(&self) -> usize {
+        let max_apdu = if self.max_apdu == 0 {
+            bacnet_sys::MAX_APDU
+        } else {
+            min(self.max_apdu, bacnet_sys::MAX_APDU)
+        };
+        max_apdu
+            .saturating_sub(FILE_DATA_OVERHEAD)
+            .clamp(1, bacnet_sys::MAX_OCTET_STRING_BYTES) as usize
+    }
+}
+
+fn octet_string(data: &[u8]) -> bacnet_sys::BACNET_OCTET_STRING {
+    let mut octet_string = bacnet_sys::BACNET_OCTET_STRING::default();
+    octet_string.value[..data.len()].copy_from_slice(data);
+    octet_string.length = data.len();
+    octet_string
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn read_file_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("read_file_ack_handler", || {
+        let mut data = bacnet_sys::BACNET_ATOMIC_READ_FILE_DATA::default();
+
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let len = unsafe {
+                bacnet_sys::arf_ack_decode_service_request(
+                    service_request,
+                    service_len.into(),
+                    &mut data,
+                )
+            };
+            if len >= 0 {
+                let record_count =
+                    if data.access == bacnet_sys::BACNET_FILE_ACCESS_METHOD_FILE_RECORD_ACCESS {
+                        unsafe { data.type_.record.RecordCount }
+                    } else {
+                        1
+                    };
+                let file_data = &data.fileData[0];
+                target.ack = Some(Ok(Ack::ReadFile(ReadFileAck {
+                    end_of_file: data.endOfFile,
+                    data: file_data.value[..file_data.length].to_vec(),
+                    record_count,
+                })));
+            } else {
+                error!("<decode failed>");
+                target.ack = Some(Err(Error::FailedToDecodeData));
+            }
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn write_file_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("write_file_ack_handler", || {
+        let mut data = bacnet_sys::BACNET_ATOMIC_WRITE_FILE_DATA::default();
+
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let len = unsafe {
+                bacnet_sys::awf_ack_decode_service_request(
+                    service_request,
+                    service_len.into(),
+                    &mut data,
+                )
+            };
+            if len >= 0 {
+                // Both access methods report the start position/record in the same place
+                let start = unsafe { data.type_.stream.fileStartPosition };
+                target.ack = Some(Ok(Ack::WriteFile { start }));
+            } else {
+                error!("<decode failed>");
+                target.ack = Some(Err(Error::FailedToDecodeData));
+            }
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
@@ -0,0 +1,276 @@
+//! Interrogating a device: reading all of its objects into an `Epics`
+//!
+//! Unlike a single `epics()` call, an `Interrogation` reports its progress, carries on past objects
+//! that can't be read, and keeps a `Checkpoint` of what has been read. When the device stops
+//! answering halfway, the checkpoint can be saved (with the `serde` feature) and the interrogation
+//! resumed from it later.
+
+use std::collections::btree_map::Entry;
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::epics::{Epics, EpicsObject, ObjectId};
+use crate::value::BACnetValue;
+use crate::{BACnetDevice, Capabilities, Error, Result};
+
+/// What an interrogation is busy with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading the elements of the device's object-list
+    ObjectList,
+    /// Reading the properties of the objects
+    Objects,
+}
+
+/// How far an interrogation has come, passed to the `on_progress()` callback
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub phase: Phase,
+    /// The number of object-list elements or objects done, including the ones that failed
+    pub done: usize,
+    pub total: usize,
+}
+
+/// An object-list element or an object that couldn't be read. The interrogation carries on
+/// without it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "kebab-case")
+)]
+pub enum ObjectError {
+    /// Element `index` of the object-list
+    ObjectList {
+        index: u64,
+        error: String,
+    },
+    Object {
+        id: ObjectId,
+        error: String,
+    },
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectError::ObjectList { index, error } => {
+                write!(f, "object-list[{}]: {}", index, error)
+            }
+            ObjectError::Object { id, error } => write!(f, "{}: {}", id, error),
+        }
+    }
+}
+
+/// Everything an interrogation has read so far
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    epics: Epics,
+    object_list_len: Option<u64>,
+    // The next object-list index to read
+    next_index: u64,
+    // The objects in the object-list, except the device object
+    object_ids: Vec<ObjectId>,
+    // The next object in `object_ids` to read
+    next_object: usize,
+    errors: Vec<ObjectError>,
+}
+
+impl Checkpoint {
+    fn new(device_id: ObjectId) -> Self {
+        Checkpoint {
+            epics: Epics {
+                device_id,
+                ..Epics::default()
+            },
+            object_list_len: None,
+            next_index: 0,
+            object_ids: vec![],
+            next_object: 0,
+            errors: vec![],
+        }
+    }
+
+    /// The instance of the interrogated device
+    pub fn device_id(&self) -> u32 {
+        self.epics.device_id.object_instance
+    }
+
+    /// The objects read so far
+    pub fn epics(&self) -> &Epics {
+        &self.epics
+    }
+
+    pub fn errors(&self) -> &[ObjectError] {
+        &self.errors
+    }
+
+    /// Whether all objects have been read (or failed)
+    pub fn is_complete(&self) -> bool {
+        matches!(self.object_list_len, Some(len) if self.next_index > len)
+            && self.next_object == self.object_ids.len()
+    }
+}
+
+/// An interrogation of a device, see `BACnetDevice::interrogate()`
+pub struct Interrogation<'a> {
+    device: &'a BACnetDevice,
+    checkpoint: Checkpoint,
+    on_progress: Option<Box<dyn FnMut(Progress) + 'a>>,
+}
+
+impl<'a> Interrogation<'a> {
+    /// Carry on where an earlier interrogation of the same device stopped
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    /// Call `f` after each object-list element and each object
+    pub fn on_progress<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Progress) + 'a,
+    {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    /// Read what hasn't been read yet, and return all objects of the device.
+    ///
+    /// Objects that can't be read are recorded in `errors()`. When the device stops answering,
+    /// the error is returned, and `checkpoint()` has everything read up to then; running again
+    /// continues from there.
+    pub fn run(&mut self) -> Result<Epics> {
+        let device = self.device;
+        let device_id = ObjectId::new(
+            bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
+            device.device_id,
+        );
+        if self.checkpoint.epics.device_id != device_id {
+            return Err(Error::CheckpointMismatch {
+                device_id: self.checkpoint.device_id(),
+            });
+        }
+
+        if let Entry::Vacant(entry) = self.checkpoint.epics.objects.entry(device_id) {
+            let properties =
+                device.try_read_all_properties(device_id.object_type, device_id.object_instance)?;
+            debug!("{:#?}", properties);
+            entry.insert(EpicsObject::new(device_id, properties));
+        }
+        if let Some(object) = self.checkpoint.epics.device() {
+            device.set_capabilities(Capabilities::from_device(object));
+        }
+
+        let len = match self.checkpoint.object_list_len {
+            Some(len) => len,
+            None => {
+                let len: u64 = device
+                    .read_prop_at(
+                        device_id.object_type,
+                        device_id.object_instance,
+                        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST,
+                        0,
+                    )?
+                    .try_into()?;
+                debug!("object-list has {} elements", len);
+                self.checkpoint.object_list_len = Some(len);
+                self.checkpoint.next_index = 1;
+                len
+            }
+        };
+
+        while self.checkpoint.next_index <= len {
+            let index = self.checkpoint.next_index;
+            match device.read_prop_at(
+                device_id.object_type,
+                device_id.object_instance,
+                bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST,
+                index as u32,
+            ) {
+                Ok(BACnetValue::ObjectId {
+                    object_type,
+                    object_instance,
+                }) => {
+                    let id = ObjectId::new(object_type, object_instance);
+                    if id != device_id {
+                        self.checkpoint.object_ids.push(id);
+                    }
+                }
+                Ok(v) => self.fail(ObjectError::ObjectList {
+                    index,
+                    error: format!("unexpected value {:?}", v),
+                }),
+                Err(err) if err.is_unreachable() => return Err(err),
+                Err(err) => self.fail(ObjectError::ObjectList {
+                    index,
+                    error: err.to_string(),
+                }),
+            }
+            self.checkpoint.next_index += 1;
+            self.report(Phase::ObjectList, index as usize, len as usize);
+        }
+
+        while self.checkpoint.next_object < self.checkpoint.object_ids.len() {
+            let id = self.checkpoint.object_ids[self.checkpoint.next_object];
+            match device.try_read_all_properties(id.object_type, id.object_instance) {
+                Ok(properties) => {
+                    self.checkpoint
+                        .epics
+                        .objects
+                        .insert(id, EpicsObject::new(id, properties));
+                }
+                Err(err) if err.is_unreachable() => return Err(err),
+                Err(err) => self.fail(ObjectError::Object {
+                    id,
+                    error: err.to_string(),
+                }),
+            }
+            self.checkpoint.next_object += 1;
+            self.report(
+                Phase::Objects,
+                self.checkpoint.next_object,
+                self.checkpoint.object_ids.len(),
+            );
+        }
+
+        Ok(self.checkpoint.epics.clone())
+    }
+
+    /// Everything read so far, to resume from
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// The object-list elements and objects that couldn't be read
+    pub fn errors(&self) -> &[ObjectError] {
+        &self.checkpoint.errors
+    }
+
+    fn fail(&mut self, error: ObjectError) {
+        warn!("{}", error);
+        self.checkpoint.errors.push(error);
+    }
+
+    fn report(&mut self, phase: Phase, done: usize, total: usize) {
+        if let Some(on_progress) = &mut self.on_progress {
+            on_progress(Progress { phase, done, total });
+        }
+    }
+}
+
+impl BACnetDevice {
+    /// Start an interrogation of the device, see `Interrogation`.
+    // let mut interrogation = dev.interrogate().on_progress(|p| eprintln!("{}/{}", p.done, p.total));
+    pub fn interrogate(&self) -> Interrogation<'_> {
+        Interrogation {
+            device: self,
+            checkpoint: Checkpoint::new(ObjectId::new(
+                bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
+                self.device_id,
+            )),
+            on_progress: None,
+        }
+    }
+}
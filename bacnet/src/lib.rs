@@ -6,22 +6,54 @@ extern crate log;
 
 use std::cmp::min;
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::ffi::CStr;
 use std::net::Ipv4Addr;
 use std::os::raw::c_char;
-use std::sync::{Mutex, Once};
-use std::{error, fmt, result};
-
-pub use epics::Epics;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::time::{Duration, Instant};
+use std::{error, fmt, io, result};
+
+pub use address::BACnetAddress;
+pub use capability::{Capabilities, ObjectTypesSupported, Service, ServicesSupported};
+pub use epics::{
+    Epics, EpicsDiff, EpicsObject, EpicsProperty, EpicsText, ObjectDiff, ObjectId, PropertyChange,
+};
+pub use errorcode::{AbortReason, ErrorClass, ErrorCode, RejectReason};
+pub use interrogation::{Checkpoint, Interrogation, ObjectError, Phase, Progress};
+pub use rpm::PropertyResult;
+use timesync::TimeSync;
 use value::BACnetValue;
 
+mod address;
+pub mod alarm;
+pub mod bbmd;
+mod capability;
+pub mod control;
 mod epics;
+mod errorcode;
+pub mod event;
+mod file;
+mod interrogation;
+pub mod object;
+pub mod ptransfer;
+pub mod router;
+mod rpm;
+pub mod server;
+pub mod timesync;
 pub mod value;
 pub mod whois;
 
 static BACNET_STACK_INIT: Once = Once::new();
 
+// Tells the connections made by connect() apart, so an instance only tears down its own
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+// The smallest max-APDU a device may have
+const MIN_APDU: u32 = 50;
+
 type RequestInvokeId = u8;
 type DeviceId = u32;
 
@@ -30,6 +62,11 @@ lazy_static! {
     /// Global tracking struct for target addresses. These are devices that we consider ourselves
     /// connected to and communicating with.
     static ref TARGET_ADDRESSES: Mutex<HashMap<DeviceId, TargetDevice>> = Mutex::new(HashMap::new());
+
+    /// Devices we're waiting for an I-Am from in `connect()`, with the segmentation they
+    /// announced. Filled in by i_am_bind_handler().
+    static ref PENDING_BINDS: Mutex<HashMap<DeviceId, Option<Segmentation>>> =
+        Mutex::new(HashMap::new());
 }
 
 //// Epics property list
@@ -52,36 +89,56 @@ enum RequestStatus {
 
 #[derive(Debug)]
 pub enum BACnetErr {
-    /// Request was rejected with the given reason code
-    Rejected { code: u8 },
+    /// Request was rejected with the given reason
+    Rejected { reason: RejectReason },
 
-    /// Request was aborted with the given reason code and text
-    Aborted { text: String, code: u8 },
+    /// Request was aborted with the given reason
+    Aborted { reason: AbortReason },
 
     /// Request resulted in an error
-    Error {
-        class_text: String,
-        class: u32,
-        text: String,
-        code: u32,
-    },
+    Error { class: ErrorClass, code: ErrorCode },
+}
+
+impl BACnetErr {
+    /// The property doesn't exist in the object (error class property, code unknown-property)
+    pub fn is_unknown_property(&self) -> bool {
+        matches!(
+            self,
+            BACnetErr::Error {
+                class: ErrorClass::Property,
+                code: ErrorCode::UnknownProperty,
+            }
+        )
+    }
+
+    /// The object doesn't exist in the device (error class object, code unknown-object)
+    pub fn is_unknown_object(&self) -> bool {
+        matches!(
+            self,
+            BACnetErr::Error {
+                class: ErrorClass::Object,
+                code: ErrorCode::UnknownObject,
+            }
+        )
+    }
+
+    /// The answer didn't fit in a single APDU, and the device can't segment it
+    pub fn is_segmentation_not_supported(&self) -> bool {
+        matches!(
+            self,
+            BACnetErr::Aborted {
+                reason: AbortReason::SegmentationNotSupported,
+            }
+        )
+    }
 }
 
 impl fmt::Display for BACnetErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            BACnetErr::Rejected { code } => write!(f, "Rejected: code {}", code),
-            BACnetErr::Aborted { text, code } => write!(f, "Aborted: {} (code {})", text, code),
-            BACnetErr::Error {
-                class_text,
-                class,
-                text,
-                code,
-            } => write!(
-                f,
-                "Error: class={} ({}) {} ({})",
-                class_text, class, text, code
-            ),
+            BACnetErr::Rejected { reason } => write!(f, "Rejected: {}", reason),
+            BACnetErr::Aborted { reason } => write!(f, "Aborted: {}", reason),
+            BACnetErr::Error { class, code } => write!(f, "Error: class={} {}", class, code),
         }
     }
 }
@@ -90,16 +147,142 @@ impl error::Error for BACnetErr {}
 
 #[derive(Debug)]
 pub enum Error {
-    CannotTurnValueIntoString { value: BACnetValue },
+    CannotTurnValueIntoString {
+        value: BACnetValue,
+    },
     FailedToDecodeData,
-    UnhandledTypeTag { tag_name: String, value_tag: u8 },
+    UnhandledTypeTag {
+        tag_name: String,
+        value_tag: u8,
+    },
     NoValueWasExtracted,
-    NotConnectedToDevice { device_id: u32 },
+    NotConnectedToDevice {
+        device_id: u32,
+    },
     FailedToBindToDevice,
+    FailedToSendRequest,
     TsmTimeout,
     ApduTimeout,
     DecodingError,
-    BacnetError { error: BACnetErr },
+    BacnetError {
+        error: BACnetErr,
+    },
+    /// The device didn't accept the password of a DeviceCommunicationControl or
+    /// ReinitializeDevice request
+    PasswordFailure,
+    /// The device refused to carry out a DeviceCommunicationControl or ReinitializeDevice request
+    ServiceRequestDenied,
+    /// The password can be at most 20 characters, and can't contain NUL characters
+    InvalidPassword,
+    /// A file record doesn't fit in a single AtomicWriteFile request to the device
+    FileRecordTooLarge {
+        len: usize,
+    },
+    /// The source of an AcknowledgeAlarm can't contain NUL characters, and has to fit in a
+    /// character string
+    InvalidAckSource,
+    /// The value can't be encoded, e.g. because a string or bit string is too long
+    FailedToEncodeValue,
+    /// CreateObject, AddListElement or RemoveListElement failed. `first_failed_element` is the
+    /// (1-based) number of the initial value or list element that caused the error, or 0 if the
+    /// error isn't related to one of them.
+    FailedElement {
+        error: BACnetErr,
+        first_failed_element: u64,
+    },
+    /// The request was answered, but no result could be extracted from the answer, e.g. because
+    /// it wasn't of the expected type or its handler failed
+    NoResult,
+    /// The device address given to the builder isn't valid
+    InvalidAddress {
+        reason: &'static str,
+    },
+    /// Device instances are at most 4194302
+    InvalidDeviceId {
+        device_id: u32,
+    },
+    /// The max-APDU given to the builder isn't between 50 and 1476
+    InvalidMaxApdu {
+        max_apdu: u32,
+    },
+    /// Sending to or receiving from a BBMD failed
+    Io(io::Error),
+    /// The BBMD didn't answer in time
+    BbmdTimeout,
+    /// The BBMD answered with a BVLC-Result NAK, e.g. 0x0020 for Read-Broadcast-Distribution-Table
+    BvlcNak {
+        result_code: u16,
+    },
+    /// The checkpoint given to `Interrogation::resume()` belongs to another device
+    CheckpointMismatch {
+        device_id: u32,
+    },
+    /// An EPICS document can't be parsed
+    InvalidEpics {
+        line: usize,
+        reason: String,
+    },
+    /// Objects of this type can't be hosted by the local device
+    UnsupportedObjectType {
+        object_type: u32,
+    },
+    /// The local device already hosts an object with this type and instance
+    ObjectExists {
+        object_type: u32,
+        object_instance: u32,
+    },
+    /// Another object of the local device already has this name
+    DuplicateObjectName {
+        name: String,
+    },
+    /// Object names can't be empty or contain NUL characters, and have to fit in a character
+    /// string
+    InvalidObjectName {
+        name: String,
+    },
+    /// The local device doesn't host the object, or the property doesn't have a value kept by the
+    /// server
+    NoLocalValue {
+        object_type: u32,
+        object_instance: u32,
+        property: u32,
+    },
+}
+
+impl Error {
+    /// The error, reject or abort reported by the device, if that's what this is
+    pub fn bacnet_error(&self) -> Option<&BACnetErr> {
+        match self {
+            Error::BacnetError { error } | Error::FailedElement { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// See `BACnetErr::is_unknown_property()`
+    pub fn is_unknown_property(&self) -> bool {
+        matches!(self.bacnet_error(), Some(error) if error.is_unknown_property())
+    }
+
+    /// See `BACnetErr::is_unknown_object()`
+    pub fn is_unknown_object(&self) -> bool {
+        matches!(self.bacnet_error(), Some(error) if error.is_unknown_object())
+    }
+
+    /// See `BACnetErr::is_segmentation_not_supported()`
+    pub fn is_segmentation_not_supported(&self) -> bool {
+        matches!(self.bacnet_error(), Some(error) if error.is_segmentation_not_supported())
+    }
+
+    /// The device didn't answer, or the request couldn't be sent to it at all
+    pub fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            Error::TsmTimeout
+                | Error::ApduTimeout
+                | Error::FailedToSendRequest
+                | Error::NotConnectedToDevice { .. }
+        )
+    }
 }
 
 impl fmt::Display for Error {
@@ -119,10 +302,67 @@ impl fmt::Display for Error {
                 write!(f, "Not connected to device {}", device_id)
             }
             FailedToBindToDevice => write!(f, "failed to bind to the device"),
+            FailedToSendRequest => write!(f, "failed to send the request"),
             TsmTimeout => write!(f, "TSM timeout"),
             ApduTimeout => write!(f, "APDU timeout"),
             DecodingError => write!(f, "decoding error"),
             BacnetError { error } => error.fmt(f),
+            PasswordFailure => write!(f, "password failure"),
+            ServiceRequestDenied => write!(f, "service request denied"),
+            InvalidPassword => write!(f, "invalid password"),
+            FileRecordTooLarge { len } => {
+                write!(
+                    f,
+                    "file record of {} bytes is too large for the device",
+                    len
+                )
+            }
+            InvalidAckSource => write!(f, "invalid acknowledgement source"),
+            FailedToEncodeValue => write!(f, "failed to encode value"),
+            FailedElement {
+                error,
+                first_failed_element,
+            } => write!(
+                f,
+                "{} (first failed element {})",
+                error, first_failed_element
+            ),
+            NoResult => write!(f, "no result was received for the request"),
+            InvalidAddress { reason } => write!(f, "invalid device address: {}", reason),
+            InvalidDeviceId { device_id } => write!(f, "invalid device ID {}", device_id),
+            InvalidMaxApdu { max_apdu } => write!(f, "invalid max-APDU {}", max_apdu),
+            Io(err) => err.fmt(f),
+            BbmdTimeout => write!(f, "timeout waiting for the BBMD"),
+            BvlcNak { result_code } => write!(f, "BVLC-Result NAK 0x{:04X}", result_code),
+            CheckpointMismatch { device_id } => {
+                write!(f, "the checkpoint is of device {}", device_id)
+            }
+            InvalidEpics { line, reason } => {
+                write!(f, "invalid EPICS at line {}: {}", line, reason)
+            }
+            UnsupportedObjectType { object_type } => {
+                write!(f, "objects of type {} can't be hosted", object_type)
+            }
+            ObjectExists {
+                object_type,
+                object_instance,
+            } => write!(
+                f,
+                "object {} is already hosted",
+                ObjectId::new(*object_type, *object_instance)
+            ),
+            DuplicateObjectName { name } => write!(f, "object name '{}' is already used", name),
+            InvalidObjectName { name } => write!(f, "invalid object name '{}'", name),
+            NoLocalValue {
+                object_type,
+                object_instance,
+                property,
+            } => write!(
+                f,
+                "no value of property {} of hosted object {}",
+                property,
+                ObjectId::new(*object_type, *object_instance)
+            ),
         }
     }
 }
@@ -146,7 +386,26 @@ pub type Result<A> = result::Result<A, Error>;
 struct TargetDevice {
     addr: bacnet_sys::BACNET_ADDRESS,
     request: Option<(RequestInvokeId, RequestStatus)>, // For tracking on-going an ongoing request
-    value: Option<Result<BACnetValue>>,                // TODO Build this into the 'request status'
+    ack: Option<Result<Ack>>,                          // TODO Build this into the 'request status'
+    rpm_supported: Option<bool>, // Whether ReadPropertyMultiple works, once we've found out
+    capabilities: Option<capability::Capabilities>,
+    connection: u64, // The connection of the BACnetDevice that connected
+}
+
+// The decoded contents of a ComplexACK, handed over from the ack handler to the request
+enum Ack {
+    Value(BACnetValue),
+    ReadFile(file::ReadFileAck),
+    WriteFile {
+        start: i32,
+    },
+    EventInformation {
+        events: Vec<alarm::EventSummary>,
+        more_events: bool,
+    },
+    AlarmSummary(Vec<alarm::AlarmSummary>),
+    PrivateTransfer(BACnetValue),
+    PropertyResults(Vec<PropertyResult>),
 }
 
 // As I understand the BACnet stack, it works by acting as another BACnet device on the network.
@@ -161,7 +420,39 @@ struct TargetDevice {
 pub struct BACnetDevice {
     pub device_id: u32,
     max_apdu: u32,
+    segmentation: Option<Segmentation>,
     addr: bacnet_sys::BACNET_ADDRESS,
+    /// Set once this instance has connected, see `TargetDevice::connection`
+    connection: Option<u64>,
+
+    /// How long `connect()` waits for the device to answer a Who-Is
+    bind_timeout: Duration,
+}
+
+/// Which segmented messages a device can send and receive, as announced in its I-Am
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Segmentation {
+    Both,
+    Transmit,
+    Receive,
+    NoSegmentation,
+}
+
+impl Segmentation {
+    pub(crate) fn from_sys(segmentation: u32) -> Option<Self> {
+        match segmentation {
+            bacnet_sys::BACNET_SEGMENTATION_SEGMENTATION_BOTH => Some(Segmentation::Both),
+            bacnet_sys::BACNET_SEGMENTATION_SEGMENTATION_TRANSMIT => Some(Segmentation::Transmit),
+            bacnet_sys::BACNET_SEGMENTATION_SEGMENTATION_RECEIVE => Some(Segmentation::Receive),
+            bacnet_sys::BACNET_SEGMENTATION_SEGMENTATION_NONE => Some(Segmentation::NoSegmentation),
+            _ => None,
+        }
+    }
 }
 
 pub type ObjectType = bacnet_sys::BACNET_OBJECT_TYPE;
@@ -172,37 +463,82 @@ impl BACnetDevice {
         BACnetDeviceBuilder::default()
     }
 
+    /// Bind to the device, so we can send requests to it.
+    ///
+    /// If the builder was given the address of the device, it's used as-is. Otherwise we send a
+    /// Who-Is for the device and wait (up to the bind timeout) for its I-Am.
     pub fn connect(&mut self) -> Result<()> {
-        BACNET_STACK_INIT.call_once(|| unsafe {
-            init_service_handlers();
-            bacnet_sys::dlenv_init();
-        });
-        // Add address
-        unsafe {
-            bacnet_sys::address_add(self.device_id, bacnet_sys::MAX_APDU, &mut self.addr);
+        init_stack();
+        if self.addr.mac_len > 0 || self.addr.net > 0 {
+            // Static binding
+            let max_apdu = if self.max_apdu > 0 {
+                self.max_apdu
+            } else {
+                bacnet_sys::MAX_APDU
+            };
+            unsafe {
+                bacnet_sys::address_add(self.device_id, max_apdu, &mut self.addr);
+            }
         }
         let mut target_addr = bacnet_sys::BACNET_ADDRESS::default();
-        // FIXME(tj): Wait until device is bound, or timeout
-        let found = unsafe {
+        let mut found = unsafe {
             bacnet_sys::address_bind_request(self.device_id, &mut self.max_apdu, &mut target_addr)
         };
+        if !found {
+            debug!("sending Who-Is for device {}", self.device_id);
+            pending_binds().insert(self.device_id, None);
+            unsafe {
+                bacnet_sys::Send_WhoIs(self.device_id as i32, self.device_id as i32);
+            }
+            let start = Instant::now();
+            while !found && start.elapsed() < self.bind_timeout {
+                receive(100);
+                found = unsafe {
+                    bacnet_sys::address_bind_request(
+                        self.device_id,
+                        &mut self.max_apdu,
+                        &mut target_addr,
+                    )
+                };
+            }
+            if let Some(Some(segmentation)) = pending_binds().remove(&self.device_id) {
+                self.segmentation = Some(segmentation);
+            }
+        }
         debug!("found = {}", found);
         if found {
-            let mut lock = TARGET_ADDRESSES.lock().unwrap();
+            let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+            let mut lock = target_addresses();
             lock.insert(
                 self.device_id,
                 TargetDevice {
                     addr: target_addr,
                     request: None,
-                    value: None,
+                    ack: None,
+                    rpm_supported: None,
+                    capabilities: None,
+                    connection,
                 },
             );
+            self.connection = Some(connection);
             Ok(())
         } else {
             Err(Error::FailedToBindToDevice)
         }
     }
 
+    /// The largest APDU the device accepts. Known once connected, or if given to the builder.
+    pub fn max_apdu(&self) -> u32 {
+        self.max_apdu
+    }
+
+    /// The segmentation support of the device, if it announced it in an I-Am while connecting, or
+    /// it's known from its capabilities.
+    pub fn segmentation(&self) -> Option<Segmentation> {
+        self.segmentation
+            .or_else(|| self.capabilities().and_then(|c| c.segmentation))
+    }
+
     // Read_Property
     //
     // Only reads the present value (property 85)
@@ -243,87 +579,131 @@ impl BACnetDevice {
         index: u32,
     ) -> Result<BACnetValue> {
         let init = std::time::Instant::now();
+        let ret = self
+            .confirmed_request(|| unsafe {
+                bacnet_sys::Send_Read_Property_Request(
+                    self.device_id,
+                    object_type,
+                    object_instance,
+                    property_id,
+                    index,
+                )
+            })
+            .and_then(|ack| match ack {
+                Some(Ack::Value(value)) => Ok(value),
+                _ => Err(Error::NoValueWasExtracted),
+            });
+
+        debug!("read_prop() finished in {:?}", init.elapsed());
+        ret
+    }
+
+    // Send a confirmed request and wait for the reply. `send` sends the actual request, and
+    // returns its invoke ID (or 0 if the request couldn't be sent).
+    //
+    // Returns what was extracted by the ack handler, or `None` for requests that are answered
+    // with a SimpleACK.
+    fn confirmed_request<F>(&self, send: F) -> Result<Option<Ack>>
+    where
+        F: FnOnce() -> RequestInvokeId,
+    {
         const TIMEOUT: u32 = 100;
-        let request_invoke_id =
-            if let Some(h) = TARGET_ADDRESSES.lock().unwrap().get_mut(&self.device_id) {
-                let request_invoke_id = unsafe {
-                    bacnet_sys::Send_Read_Property_Request(
-                        self.device_id,
-                        object_type,
-                        object_instance,
-                        property_id,
-                        index,
-                    )
-                };
-                h.request = Some((request_invoke_id, RequestStatus::Ongoing));
-                request_invoke_id
-            } else {
-                return Err(Error::NotConnectedToDevice {
-                    device_id: self.device_id,
-                });
-            };
+        let request_invoke_id = if let Some(h) = target_addresses().get_mut(&self.device_id) {
+            let request_invoke_id = send();
+            if request_invoke_id == 0 {
+                return Err(Error::FailedToSendRequest);
+            }
+            h.request = Some((request_invoke_id, RequestStatus::Ongoing));
+            h.ack = None;
+            request_invoke_id
+        } else {
+            return Err(Error::NotConnectedToDevice {
+                device_id: self.device_id,
+            });
+        };
 
-        let mut src = bacnet_sys::BACNET_ADDRESS::default();
-        let mut rx_buf = [0u8; bacnet_sys::MAX_MPDU as usize];
         let start = std::time::Instant::now();
         loop {
-            // TODO(tj): Consider pulling the "driving forward the internal state machine" stuff
-            // into an inner method here. We need it for EPICS as well.
-            let pdu_len = unsafe {
-                bacnet_sys::bip_receive(
-                    &mut src,
-                    &mut rx_buf as *mut _,
-                    bacnet_sys::MAX_MPDU as u16,
-                    TIMEOUT,
-                )
-            };
-            if pdu_len > 0 {
-                unsafe { bacnet_sys::npdu_handler(&mut src, &mut rx_buf as *mut _, pdu_len) }
-            }
+            receive(TIMEOUT);
 
-            // FIXME(tj): Need to do tsm_invoke_id_free() and tsm_invoke_id_failed() in this loop
-            // as well.
             if unsafe { bacnet_sys::tsm_invoke_id_free(request_invoke_id) } {
                 break;
             }
             if unsafe { bacnet_sys::tsm_invoke_id_failed(request_invoke_id) } {
+                self.abandon_request(request_invoke_id);
                 return Err(Error::TsmTimeout);
             }
 
             if start.elapsed().as_secs() > 3 {
                 // FIXME(tj): A better timeout here...
+                self.abandon_request(request_invoke_id);
                 return Err(Error::ApduTimeout);
             }
         }
 
-        let ret = {
-            let mut lock = TARGET_ADDRESSES.lock().unwrap();
-            let h = lock.get_mut(&self.device_id).unwrap();
-            let request_status = h.request.take();
-            match request_status.unwrap().1 {
-                RequestStatus::Done => h
-                    .value
-                    .take()
-                    .unwrap_or_else(|| Err(Error::NoValueWasExtracted)),
-                RequestStatus::Ongoing => panic!(
-                    "attempting to extract a value, but the request is still marked as on-going"
-                ),
-                RequestStatus::Error(err) => Err(err.into()),
+        let mut lock = target_addresses();
+        let h = lock
+            .get_mut(&self.device_id)
+            .ok_or(Error::NotConnectedToDevice {
+                device_id: self.device_id,
+            })?;
+        match h.request.take() {
+            Some((invoke_id, RequestStatus::Done)) if invoke_id == request_invoke_id => {
+                h.ack.take().transpose()
             }
-        };
+            Some((invoke_id, RequestStatus::Error(err))) if invoke_id == request_invoke_id => {
+                Err(err.into())
+            }
+            // The transaction ended without any of our handlers recording a result
+            _ => Err(Error::NoResult),
+        }
+    }
 
-        debug!("read_prop() finished in {:?}", init.elapsed());
-        ret
+    // Give up on a request that didn't complete: free its invoke ID, and forget about it so a late
+    // answer isn't taken for the answer of the next request
+    fn abandon_request(&self, invoke_id: RequestInvokeId) {
+        unsafe { bacnet_sys::tsm_free_invoke_id(invoke_id) };
+        if let Some(target) = target_addresses().get_mut(&self.device_id) {
+            if matches!(target.request, Some((id, _)) if id == invoke_id) {
+                target.request = None;
+            }
+        }
     }
 
-    /// Read all required properties for a given object-type and object-instance
+    /// Read all properties for a given object-type and object-instance with ReadProperty
     ///
-    /// The BACnet stack internally has a list of required properties for a given object-type, and
-    /// this function will simply walk over every single one and call `read_prop()` on it.
+    /// The properties to read are taken from the object's `property-list`, which includes
+    /// proprietary properties. For objects without one, the BACnet stack's internal list of
+    /// required and optional properties for the object-type is used instead, and this function
+    /// will simply walk over every single one and call `read_prop()` on it.
     pub fn read_properties(
         &self,
         object_type: bacnet_sys::BACNET_OBJECT_TYPE,
         object_instance: u32,
+    ) -> HashMap<ObjectPropertyId, BACnetValue> {
+        let properties = match self.read_property_list(object_type, object_instance) {
+            Ok(properties) => properties,
+            Err(err) => {
+                debug!("no property-list, reading the known properties: {}", err);
+                return self.read_known_properties(object_type, object_instance);
+            }
+        };
+
+        let mut ret = HashMap::with_capacity(properties.len());
+        for property in properties {
+            if let Err(err) = self.read_single(object_type, object_instance, property, &mut ret) {
+                error!("Failed to get property {}", err);
+                break;
+            }
+        }
+        ret
+    }
+
+    // Read the required and optional properties that the stack knows of for the object-type
+    pub(crate) fn read_known_properties(
+        &self,
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
     ) -> HashMap<ObjectPropertyId, BACnetValue> {
         let mut special_property_list = bacnet_sys::special_property_list_t::default();
 
@@ -352,14 +732,7 @@ impl BACnetDevice {
                     ret.insert(prop, v);
                 }
                 Err(Error::BacnetError { error: bacnet_err }) => {
-                    if matches!(
-                        bacnet_err,
-                        BACnetErr::Error {
-                            class: 2,
-                            code: 32,
-                            ..
-                        }
-                    ) {
+                    if bacnet_err.is_unknown_property() {
                         debug!("{}", bacnet_err);
                     } else {
                         warn!("{}", bacnet_err);
@@ -386,29 +759,28 @@ impl BACnetDevice {
                     ret.insert(prop, v);
                 }
                 Err(Error::BacnetError { error: bacnet_err }) => {
-                    match bacnet_err {
-                        BACnetErr::Aborted { code, .. } if code == 4 => {
-                            // code == 4 is "segmentation not supported". This is an array
-                            let len: Result<u64> = self
-                                .read_prop_at(object_type, object_instance, prop, 0)
-                                .and_then(|x| x.try_into());
-
-                            if let Ok(len) = len {
-                                let mut ary = Vec::with_capacity(len as usize);
-                                for i in 0..len {
-                                    if let Ok(val) = self.read_prop_at(
-                                        object_type,
-                                        object_instance,
-                                        prop,
-                                        i as u32 + 1,
-                                    ) {
-                                        ary.push(val);
-                                    }
+                    if bacnet_err.is_segmentation_not_supported() {
+                        // This is an array that doesn't fit in a single APDU
+                        let len: Result<u64> = self
+                            .read_prop_at(object_type, object_instance, prop, 0)
+                            .and_then(|x| x.try_into());
+
+                        if let Ok(len) = len {
+                            let mut ary = Vec::with_capacity(len as usize);
+                            for i in 0..len {
+                                if let Ok(val) = self.read_prop_at(
+                                    object_type,
+                                    object_instance,
+                                    prop,
+                                    i as u32 + 1,
+                                ) {
+                                    ary.push(val);
                                 }
-                                ret.insert(prop, BACnetValue::Array(ary));
                             }
+                            ret.insert(prop, BACnetValue::Array(ary));
                         }
-                        _ => debug!("{:?}", bacnet_err),
+                    } else {
+                        debug!("{:?}", bacnet_err);
                     }
                 }
                 Err(err) => {
@@ -422,71 +794,46 @@ impl BACnetDevice {
     }
 
     /// Scan the device for all available tags and produce an `Epics` object
+    ///
+    /// Objects are read with ReadPropertyMultiple where possible, see `read_all_properties()`.
+    /// Objects that can't be read are left out; `interrogate()` reports them, as well as the
+    /// progress of the scan.
     pub fn epics(&self) -> Result<Epics> {
-        let device_props =
-            self.read_properties(bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE, self.device_id);
-
-        // Read the object-list
-        let len: u64 = self
-            .read_prop_at(
-                bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
-                self.device_id,
-                bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST,
-                0,
-            )?
-            .try_into()?;
-
-        let mut object_ids = Vec::with_capacity(len as usize);
-        for i in 2..len + 1 {
-            match self.read_prop_at(
-                bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
-                self.device_id,
-                bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST,
-                i as u32,
-            )? {
-                BACnetValue::ObjectId {
-                    object_type,
-                    object_instance,
-                } => {
-                    object_ids.push((object_type, object_instance));
-                }
-                v => error!("Unexpected type when reading object-list {:?}", v),
-            }
-        }
-
-        debug!("{:#?}", device_props);
-        debug!("object-list has {} elements", len);
-        debug!("{:#?}", object_ids);
-
-        let mut objects = Vec::with_capacity(len as usize);
-        for (object_type, object_instance) in object_ids {
-            let object_props = self.read_properties(object_type, object_instance);
-            objects.push(object_props);
-        }
-        debug!("Objects:\n{:#?}", objects);
-
-        // Populate
-        let device = device_props
-            .into_iter()
-            .map(|(id, val)| (cstr(unsafe { bacnet_sys::bactext_property_name(id) }), val))
-            .collect::<HashMap<_, _>>();
-
-        let object_list = objects
-            .into_iter()
-            .map(|obj| {
-                obj.into_iter()
-                    .map(|(id, val)| (cstr(unsafe { bacnet_sys::bactext_property_name(id) }), val))
-                    .collect::<HashMap<_, _>>()
-            })
-            .collect::<Vec<_>>();
+        let mut interrogation = self.interrogate();
+        let epics = interrogation.run()?;
+        debug!("Objects:\n{:#?}", epics.objects);
+        Ok(epics)
+    }
 
-        Ok(Epics {
-            device,
-            object_list,
-        })
+    /// Send a Time-Synchronization (or UTC-Time-Synchronization) directly to this device
+    pub fn time_sync(&self, time_sync: &TimeSync) -> Result<()> {
+        let mut addr = if let Some(h) = target_addresses().get(&self.device_id) {
+            h.addr
+        } else {
+            return Err(Error::NotConnectedToDevice {
+                device_id: self.device_id,
+            });
+        };
+        time_sync.send_to(&mut addr);
+        Ok(())
     }
 
+    /// Stop communicating with the device: forget it, cancel any outstanding request and remove it
+    /// from the stack's address cache, making room for other devices. Also done on drop, if this
+    /// instance is the one that last connected to the device.
+    ///
+    /// Any other `BACnetDevice` with the same device ID is disconnected as well.
     pub fn disconnect(&self) {
+        let target = target_addresses().remove(&self.device_id);
+        if let Some(TargetDevice {
+            request: Some((invoke_id, _)),
+            ..
+        }) = target
+        {
+            debug!("cancelling invoke_id = {}", invoke_id);
+            unsafe { bacnet_sys::tsm_free_invoke_id(invoke_id) };
+        }
+        pending_binds().remove(&self.device_id);
         unsafe {
             bacnet_sys::address_remove_device(self.device_id);
         }
@@ -495,51 +842,97 @@ impl BACnetDevice {
 
 impl Drop for BACnetDevice {
     fn drop(&mut self) {
-        // FIXME(tj): Call address_remove()
-        info!("disconnecting");
+        // Leave the device alone if this instance never connected, or if another one has
+        // connected to it since
+        let connected = match (self.connection, target_addresses().get(&self.device_id)) {
+            (Some(connection), Some(target)) => target.connection == connection,
+            _ => false,
+        };
+        if connected {
+            info!("disconnecting");
+            self.disconnect();
+        }
     }
 }
 
 // ./bacrp 1025 analog-value 22 present-value --mac 192.168.10.96 --dnet 5 --dadr 14
 #[derive(Debug)]
 pub struct BACnetDeviceBuilder {
-    ip: Ipv4Addr,
-    dnet: u16,
-    dadr: u8,
+    /// The B/IP address of the device (or its router), `None` to find it with Who-Is on connect.
+    ip: Option<Ipv4Addr>,
     port: u16,
+    /// The MAC address of the device (or its router) in any other form than B/IP
+    mac: Option<Vec<u8>>,
+    /// The network of a device behind a router, 0 for a device on the local network
+    dnet: u16,
+    /// The MAC address of a device behind a router, on network `dnet`
+    adr: Vec<u8>,
     device_id: u32,
+    max_apdu: Option<u32>,
+    segmentation: Option<Segmentation>,
+    bind_timeout: Duration,
 }
 
 impl Default for BACnetDeviceBuilder {
     fn default() -> Self {
         Self {
-            ip: Ipv4Addr::LOCALHOST,
-            dnet: 0,
-            dadr: 0,
+            ip: None,
             port: 0xBAC0,
+            mac: None,
+            dnet: 0,
+            adr: vec![],
             device_id: 0,
+            max_apdu: None,
+            segmentation: None,
+            bind_timeout: Duration::from_secs(3),
         }
     }
 }
 
 impl BACnetDeviceBuilder {
+    /// The IP address of the device, or of the router to reach it through. Use `port()` for a
+    /// non-standard UDP port.
     pub fn ip(mut self, ip: Ipv4Addr) -> Self {
-        self.ip = ip;
+        self.ip = Some(ip);
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// The MAC address (of up to 7 bytes) of the device, or of the router to reach it through,
+    /// instead of a B/IP address set with `ip()` and `port()`.
+    pub fn mac(mut self, mac: &[u8]) -> Self {
+        self.mac = Some(mac.to_vec());
         self
     }
 
+    /// The network number of a device behind a router. Default: 0, a device on the local network
     pub fn dnet(mut self, dnet: u16) -> Self {
         self.dnet = dnet;
         self
     }
 
-    pub fn dadr(mut self, dadr: u8) -> Self {
-        self.dadr = dadr;
+    /// The 1-byte MAC address (e.g. MS/TP) of a device behind a router
+    pub fn dadr(self, dadr: u8) -> Self {
+        self.adr(&[dadr])
+    }
+
+    /// The MAC address (of up to 7 bytes) of a device behind a router
+    pub fn adr(mut self, adr: &[u8]) -> Self {
+        self.adr = adr.to_vec();
         self
     }
 
-    pub fn port(mut self, port: u16) -> Self {
-        self.port = port;
+    /// The complete address of the device, instead of `ip()`, `port()`, `mac()`, `dnet()` and
+    /// `adr()`.
+    pub fn address(mut self, address: BACnetAddress) -> Self {
+        self.ip = None;
+        self.mac = Some(address.mac).filter(|mac| !mac.is_empty());
+        self.dnet = address.net;
+        self.adr = address.adr;
         self
     }
 
@@ -548,28 +941,181 @@ impl BACnetDeviceBuilder {
         self
     }
 
-    pub fn build(self) -> BACnetDevice {
+    /// The largest APDU the device accepts, if known. Default: the largest one we support
+    pub fn max_apdu(mut self, max_apdu: u32) -> Self {
+        self.max_apdu = Some(max_apdu);
+        self
+    }
+
+    /// The segmentation support of the device, if known
+    pub fn segmentation(mut self, segmentation: Segmentation) -> Self {
+        self.segmentation = Some(segmentation);
+        self
+    }
+
+    /// How long `connect()` waits for an I-Am when the address isn't given. Default: 3s
+    pub fn bind_timeout(mut self, bind_timeout: Duration) -> Self {
+        self.bind_timeout = bind_timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<BACnetDevice> {
         let BACnetDeviceBuilder {
             ip,
-            dnet,
-            dadr,
             port,
+            mac,
+            dnet,
+            adr,
             device_id,
+            max_apdu,
+            segmentation,
+            bind_timeout,
         } = self;
-        let mut addr = bacnet_sys::BACNET_ADDRESS::default();
-        addr.mac[..4].copy_from_slice(&ip.octets());
-        addr.mac[4] = (port >> 8) as u8;
-        addr.mac[5] = (port & 0xff) as u8;
-        addr.mac_len = 6;
-        addr.net = dnet;
-        addr.adr[0] = dadr;
-        addr.len = 1;
-
-        BACnetDevice {
+        let invalid = |reason| Err(Error::InvalidAddress { reason });
+
+        let mac = match (ip, mac) {
+            (Some(_), Some(_)) => return invalid("both an IP and a MAC address were given"),
+            (Some(_), None) if port == 0 => return invalid("the UDP port can't be 0"),
+            (Some(ip), None) => {
+                let mut mac = ip.octets().to_vec();
+                mac.extend_from_slice(&port.to_be_bytes());
+                mac
+            }
+            (None, Some(mac)) => mac,
+            (None, None) => vec![],
+        };
+        if dnet == bacnet_sys::BACNET_BROADCAST_NETWORK as u16 {
+            return invalid("the global broadcast network isn't a device address");
+        }
+        if dnet == 0 && !adr.is_empty() {
+            return invalid("a remote address requires a network number");
+        }
+        if dnet != 0 && adr.is_empty() {
+            return invalid("a device on a remote network requires a remote address");
+        }
+        if device_id >= bacnet_sys::BACNET_MAX_INSTANCE {
+            return Err(Error::InvalidDeviceId { device_id });
+        }
+        if let Some(max_apdu) = max_apdu {
+            if !(MIN_APDU..=bacnet_sys::MAX_APDU).contains(&max_apdu) {
+                return Err(Error::InvalidMaxApdu { max_apdu });
+            }
+        }
+
+        let addr = bacnet_sys::BACNET_ADDRESS::try_from(&BACnetAddress {
+            mac,
+            net: dnet,
+            adr,
+        })?;
+
+        Ok(BACnetDevice {
             device_id,
-            max_apdu: 0,
+            max_apdu: max_apdu.unwrap_or(0),
+            segmentation,
             addr,
+            connection: None,
+            bind_timeout,
+        })
+    }
+}
+
+/// Drive the BACnet stack forward for (at least) the given duration, processing any incoming
+/// requests, e.g. Time-Synchronization (see `timesync::subscribe()`).
+pub fn poll(duration: Duration) {
+    init_stack();
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        receive(100);
+    }
+}
+
+// Initialize the stack and our service handlers, the first time we need it
+fn init_stack() {
+    BACNET_STACK_INIT.call_once(|| unsafe {
+        init_service_handlers();
+        bacnet_sys::dlenv_init();
+    });
+}
+
+// Wait (up to `timeout` millis) for a single PDU, and have the stack handle it. The result is
+// delivered through the service handlers.
+fn receive(timeout: u32) {
+    let mut src = bacnet_sys::BACNET_ADDRESS::default();
+    let mut rx_buf = [0u8; bacnet_sys::MAX_MPDU as usize];
+    let pdu_len = unsafe {
+        bacnet_sys::bip_receive(
+            &mut src,
+            &mut rx_buf as *mut _,
+            bacnet_sys::MAX_MPDU as u16,
+            timeout,
+        )
+    };
+    if pdu_len == 0 {
+        return;
+    }
+    let pdu = &mut rx_buf[..pdu_len as usize];
+    if !router::handle_network_message(&src, pdu) && !object::handle_error_pdu(&src, pdu) {
+        unsafe { bacnet_sys::npdu_handler(&mut src, &mut rx_buf as *mut _, pdu_len) }
+    }
+}
+
+// Send a confirmed request to a bound device, for services where the stack doesn't have a
+// Send_*_Request() function. `encode` encodes the APDU with the given invoke ID into the buffer,
+// and returns the encoded length.
+//
+// Like the stack's own functions, returns the invoke ID, or 0 if the request couldn't be sent.
+fn send_confirmed_request<F>(device_id: u32, encode: F) -> RequestInvokeId
+where
+    F: FnOnce(&mut [u8], RequestInvokeId) -> i32,
+{
+    let mut dest = bacnet_sys::BACNET_ADDRESS::default();
+    let mut my_address = bacnet_sys::BACNET_ADDRESS::default();
+    let mut npdu_data = bacnet_sys::BACNET_NPDU_DATA::default();
+    let mut max_apdu = 0;
+    let mut buf = [0u8; bacnet_sys::MAX_PDU as usize];
+
+    unsafe {
+        if !bacnet_sys::dcc_communication_enabled()
+            || !bacnet_sys::address_get_by_device(device_id, &mut max_apdu, &mut dest)
+        {
+            return 0;
+        }
+        let invoke_id = bacnet_sys::tsm_next_free_invokeID();
+        if invoke_id == 0 {
+            return 0;
+        }
+
+        bacnet_sys::bip_get_my_address(&mut my_address);
+        bacnet_sys::npdu_encode_npdu_data(
+            &mut npdu_data,
+            true,
+            bacnet_sys::BACNET_MESSAGE_PRIORITY_MESSAGE_PRIORITY_NORMAL,
+        );
+        let npdu_len = bacnet_sys::npdu_encode_pdu(
+            buf.as_mut_ptr(),
+            &mut dest,
+            &mut my_address,
+            &mut npdu_data,
+        );
+        let len = encode(&mut buf[npdu_len as usize..], invoke_id);
+        let pdu_len = npdu_len + len;
+        if len <= 0 || pdu_len as u32 >= max_apdu {
+            warn!(
+                "request doesn't fit in max APDU {} of device {}",
+                max_apdu, device_id
+            );
+            bacnet_sys::tsm_free_invoke_id(invoke_id);
+            return 0;
         }
+        bacnet_sys::tsm_set_confirmed_unsegmented_transaction(
+            invoke_id,
+            &mut dest,
+            &mut npdu_data,
+            buf.as_mut_ptr(),
+            pdu_len as u16,
+        );
+        bacnet_sys::bip_send_pdu(&mut dest, &mut npdu_data, buf.as_mut_ptr(), pdu_len as u32);
+        invoke_id
     }
 }
 
@@ -580,47 +1126,187 @@ extern "C" fn my_readprop_ack_handler(
     src: *mut bacnet_sys::BACNET_ADDRESS,
     service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
 ) {
-    let mut data: bacnet_sys::BACNET_READ_PROPERTY_DATA =
-        bacnet_sys::BACNET_READ_PROPERTY_DATA::default();
+    catch_panic("my_readprop_ack_handler", || {
+        let mut data: bacnet_sys::BACNET_READ_PROPERTY_DATA =
+            bacnet_sys::BACNET_READ_PROPERTY_DATA::default();
+
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            // Decode the data
+            let len = unsafe {
+                bacnet_sys::rp_ack_decode_service_request(
+                    service_request,
+                    service_len.into(),
+                    &mut data as *mut _,
+                )
+            };
+            if len >= 0 {
+                // XXX Consider moving data decoding out. We should probably just stick to getting
+                // the raw data, putting it somewhere and let someone else decode it.
+                let decoded = decode_data(data).map(Ack::Value);
+                target.ack = Some(decoded);
+            } else {
+                error!("<decode failed>");
+                target.ack = Some(Err(Error::FailedToDecodeData));
+            }
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
 
-    let invoke_id = unsafe { (*service_data).invoke_id };
-    let mut lock = TARGET_ADDRESSES.lock().unwrap();
-    if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
-        // Decode the data
-        let len = unsafe {
-            bacnet_sys::rp_ack_decode_service_request(
-                service_request,
-                service_len.into(),
-                &mut data as *mut _,
-            )
-        };
-        if len >= 0 {
-            // XXX Consider moving data decoding out. We should probably just stick to getting
-            // the raw data, putting it somewhere and let someone else decode it.
-            let decoded = decode_data(data);
-            target.value = Some(decoded);
-        } else {
-            error!("<decode failed>");
-            target.value = Some(Err(Error::FailedToDecodeData));
+// Decode the value of a property. An array or list comes as its elements one after another, and is
+// returned as an Array.
+fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValue> {
+    let data_len = data.application_data_len.max(0) as usize;
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while offset < data_len {
+        let (value, len) = decode_value(
+            unsafe { data.application_data.add(offset) },
+            (data_len - offset) as u32,
+            data.object_type,
+            data.object_property,
+        )?;
+        if len == 0 {
+            return Err(Error::DecodingError);
         }
-        target.request = Some((invoke_id, RequestStatus::Done));
+        values.push(value);
+        offset += len;
     }
+    Ok(collect_elements(
+        values,
+        data.object_property,
+        data.array_index,
+    ))
 }
 
-fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValue> {
+// The decoded elements of a property value. A whole array or list is always an Array, even if it
+// only holds zero or one element, so it can't be mistaken for a scalar.
+pub(crate) fn collect_elements(
+    mut values: Vec<BACnetValue>,
+    property: ObjectPropertyId,
+    array_index: u32,
+) -> BACnetValue {
+    if array_index == bacnet_sys::BACNET_ARRAY_ALL && is_array_or_list(property) {
+        return BACnetValue::Array(values);
+    }
+    match values.len() {
+        0 => BACnetValue::Null,
+        1 => values.remove(0),
+        _ => BACnetValue::Array(values),
+    }
+}
+
+// Properties that are a BACnetARRAY or a BACnetLIST
+fn is_array_or_list(property: ObjectPropertyId) -> bool {
+    is_array(property) || is_list(property)
+}
+
+// Properties that are a BACnetARRAY, whose elements can be read by index
+pub(crate) fn is_array(property: ObjectPropertyId) -> bool {
+    matches!(
+        property,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_ACTION
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_ACTION_TEXT
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_AUTO_SLAVE_DISCOVERY
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_COMMAND_TIME_ARRAY
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_CONFIGURATION_FILES
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_EVENT_MESSAGE_TEXTS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_EVENT_MESSAGE_TEXTS_CONFIG
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_EVENT_TIME_STAMPS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_EXCEPTION_SCHEDULE
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_EXECUTION_DELAY
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_GROUP_MEMBERS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_IP_DNS_SERVER
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_LINK_SPEEDS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_PRIORITY_ARRAY
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_PROPERTY_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SHED_LEVELS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SHED_LEVEL_DESCRIPTIONS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SLAVE_PROXY_ENABLE
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_STATE_TEXT
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_STRUCTURED_OBJECT_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SUBORDINATE_ANNOTATIONS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SUBORDINATE_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_TAGS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_VALUE_SOURCE_ARRAY
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_WEEKLY_SCHEDULE
+    )
+}
+
+// Properties that are a BACnetLIST
+fn is_list(property: ObjectPropertyId) -> bool {
+    matches!(
+        property,
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_ACTIVE_COV_SUBSCRIPTIONS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_ACTIVE_VT_SESSIONS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_ALARM_VALUES
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_BBMD_BROADCAST_DISTRIBUTION_TABLE
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_BBMD_FOREIGN_DEVICE_TABLE
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_DATE_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_DEVICE_ADDRESS_BINDING
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_FAULT_VALUES
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_LIST_OF_GROUP_MEMBERS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_LIST_OF_OBJECT_PROPERTY_REFERENCES
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_LOG_BUFFER
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_MANUAL_SLAVE_ADDRESS_BINDING
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_MEMBER_OF
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_RECIPIENT_LIST
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_RESTART_NOTIFICATION_RECIPIENTS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_ROUTING_TABLE
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_SLAVE_ADDRESS_BINDING
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_TIME_SYNCHRONIZATION_RECIPIENTS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_UTC_TIME_SYNCHRONIZATION_RECIPIENTS
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_VT_CLASSES_SUPPORTED
+            | bacnet_sys::BACNET_PROPERTY_ID_PROP_ZONE_MEMBERS
+    )
+}
+
+// Decode application tagged data that isn't tied to a property (e.g. private transfer parameters).
+// Returns Null if there's no data and an Array if there's more than one value.
+pub(crate) fn decode_values(data: &mut [u8]) -> Result<BACnetValue> {
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (value, len) = decode_value(
+            data[offset..].as_mut_ptr(),
+            (data.len() - offset) as u32,
+            bacnet_sys::BACNET_OBJECT_TYPE_MAX_BACNET_OBJECT_TYPE,
+            bacnet_sys::BACNET_PROPERTY_ID_MAX_BACNET_PROPERTY_ID,
+        )?;
+        if len == 0 {
+            return Err(Error::DecodingError);
+        }
+        values.push(value);
+        offset += len;
+    }
+    Ok(match values.len() {
+        0 => BACnetValue::Null,
+        1 => values.remove(0),
+        _ => BACnetValue::Array(values),
+    })
+}
+
+// Decode a single application tagged value, returning the value and the number of bytes used.
+// The object type and property are used to look up names of enumerated values.
+fn decode_value(
+    appdata: *mut u8,
+    appdata_len: u32,
+    object_type: u32,
+    object_property: u32,
+) -> Result<(BACnetValue, usize)> {
     let mut value = bacnet_sys::BACNET_APPLICATION_DATA_VALUE::default();
-    let appdata = data.application_data;
-    let appdata_len = data.application_data_len;
 
-    let len = unsafe {
-        bacnet_sys::bacapp_decode_application_data(appdata, appdata_len as u32, &mut value)
-    };
+    let len =
+        unsafe { bacnet_sys::bacapp_decode_application_data(appdata, appdata_len, &mut value) };
 
     if len == bacnet_sys::BACNET_STATUS_ERROR {
         return Err(Error::DecodingError);
     }
 
-    Ok(match value.tag as u32 {
+    let value = match value.tag as u32 {
         bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_NULL => BACnetValue::Null,
         bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_BOOLEAN => {
             BACnetValue::Bool(unsafe { value.type_.Boolean })
@@ -649,6 +1335,10 @@ fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValu
             });
             BACnetValue::String(s)
         }
+        bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_OCTET_STRING => {
+            let octets = unsafe { &value.type_.Octet_String };
+            BACnetValue::Bytes(octets.value[..octets.length].to_vec())
+        }
         bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_BIT_STRING => {
             let nbits = unsafe { bacnet_sys::bitstring_bits_used(&mut value.type_.Bit_String) };
             // info!("Number of bits: {}", nbits);
@@ -671,7 +1361,7 @@ fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValu
             //
             // It should return the numbers of characters written so we can permute it to a String
             let enum_val = unsafe { value.type_.Enumerated };
-            let s = match data.object_property {
+            let s = match object_property {
                 bacnet_sys::BACNET_PROPERTY_ID_PROP_UNITS => {
                     if enum_val < 256 {
                         Some(cstr(unsafe {
@@ -692,7 +1382,7 @@ fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValu
                 }
                 bacnet_sys::BACNET_PROPERTY_ID_PROP_PRESENT_VALUE
                 | bacnet_sys::BACNET_PROPERTY_ID_PROP_RELINQUISH_DEFAULT => {
-                    if data.object_type < bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_PROPRIETARY_MIN {
+                    if object_type < bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_PROPRIETARY_MIN {
                         Some(cstr(unsafe {
                             bacnet_sys::bactext_binary_present_value_name(enum_val)
                         }))
@@ -799,16 +1489,56 @@ fn decode_data(data: bacnet_sys::BACNET_READ_PROPERTY_DATA) -> Result<BACnetValu
                 value_tag: value.tag,
             });
         }
-    })
+    };
+    Ok((value, len as usize))
 }
 
-#[no_mangle]
-extern "C" fn my_readpropmultiple_ack_handler(
-    service_request: u16,
+// Like the stack's handler_i_am_bind(), but also records the segmentation of devices we're
+// connecting to.
+extern "C" fn i_am_bind_handler(
+    service_request: *mut u8,
+    _service_len: u16,
     src: *mut bacnet_sys::BACNET_ADDRESS,
-    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
 ) {
-    let mut data = bacnet_sys::BACNET_READ_ACCESS_DATA::default();
+    catch_panic("i_am_bind_handler", || {
+        let mut device_id = 0;
+        let mut max_apdu = 0;
+        let mut segmentation = 0;
+        let mut vendor_id = 0;
+        let len = unsafe {
+            bacnet_sys::iam_decode_service_request(
+                service_request,
+                &mut device_id,
+                &mut max_apdu,
+                &mut segmentation,
+                &mut vendor_id,
+            )
+        };
+        if len <= 0 {
+            return;
+        }
+        unsafe { bacnet_sys::address_add_binding(device_id, max_apdu, src) };
+        if let Some(pending) = pending_binds().get_mut(&device_id) {
+            *pending = Segmentation::from_sys(segmentation as u32);
+        }
+        whois::discovered(whois::IAmDevice {
+            device_id,
+            max_apdu,
+            segmentation: Segmentation::from_sys(segmentation as u32),
+            vendor_id,
+            address: BACnetAddress::from(unsafe { &*src }),
+        });
+    });
+}
+
+#[no_mangle]
+extern "C" fn my_simple_ack_handler(src: *mut bacnet_sys::BACNET_ADDRESS, invoke_id: u8) {
+    catch_panic("my_simple_ack_handler", || {
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
 }
 
 #[no_mangle]
@@ -818,22 +1548,23 @@ extern "C" fn my_error_handler(
     error_class: bacnet_sys::BACNET_ERROR_CLASS,
     error_code: bacnet_sys::BACNET_ERROR_CODE,
 ) {
-    let mut lock = TARGET_ADDRESSES.lock().unwrap();
-    if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
-        let error_class_str = cstr(unsafe { bacnet_sys::bactext_error_class_name(error_class) });
-        let error_code_str = cstr(unsafe { bacnet_sys::bactext_error_code_name(error_code) });
-        debug!(
-            "BACnet error: error_class={} ({}) error_code={} ({})",
-            error_class, error_class_str, error_code, error_code_str,
-        );
-        let err = BACnetErr::Error {
-            class_text: error_class_str,
-            class: error_class,
-            text: error_code_str,
-            code: error_code,
-        };
-        target.request = Some((invoke_id, RequestStatus::Error(err)));
-    }
+    catch_panic("my_error_handler", || {
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let err = bacnet_error(error_class, error_code);
+            target.request = Some((invoke_id, RequestStatus::Error(err)));
+        }
+    });
+}
+
+fn bacnet_error(
+    error_class: bacnet_sys::BACNET_ERROR_CLASS,
+    error_code: bacnet_sys::BACNET_ERROR_CODE,
+) -> BACnetErr {
+    let class = ErrorClass::from_u32(error_class);
+    let code = ErrorCode::from_u32(error_code);
+    debug!("BACnet error: error_class={} error_code={}", class, code);
+    BACnetErr::Error { class, code }
 }
 
 #[no_mangle]
@@ -843,22 +1574,20 @@ extern "C" fn my_abort_handler(
     abort_reason: u8,
     server: bool,
 ) {
-    let _ = server;
-    let _ = src;
-    let mut lock = TARGET_ADDRESSES.lock().unwrap();
-    if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
-        let abort_text =
-            cstr(unsafe { bacnet_sys::bactext_abort_reason_name(abort_reason as u32) });
-        debug!(
-            "aborted invoke_id = {} abort_reason = {} ({})",
-            invoke_id, abort_text, abort_reason
-        );
-        let err_abort = BACnetErr::Aborted {
-            text: abort_text,
-            code: abort_reason,
-        };
-        target.request = Some((invoke_id, RequestStatus::Error(err_abort)));
-    }
+    catch_panic("my_abort_handler", || {
+        let _ = server;
+        let _ = src;
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let reason = AbortReason::from_u32(abort_reason.into());
+            debug!(
+                "aborted invoke_id = {} abort_reason = {}",
+                invoke_id, reason
+            );
+            let err_abort = BACnetErr::Aborted { reason };
+            target.request = Some((invoke_id, RequestStatus::Error(err_abort)));
+        }
+    });
 }
 
 #[no_mangle]
@@ -867,19 +1596,67 @@ extern "C" fn my_reject_handler(
     invoke_id: u8,
     reject_reason: u8,
 ) {
-    let _ = src;
+    catch_panic("my_reject_handler", || {
+        let _ = src;
+
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            target.request = Some((
+                invoke_id,
+                RequestStatus::Error(BACnetErr::Rejected {
+                    reason: RejectReason::from_u32(reject_reason.into()),
+                }),
+            ));
+        }
+    });
+}
 
-    let mut lock = TARGET_ADDRESSES.lock().unwrap();
-    if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
-        target.request = Some((
-            invoke_id,
-            RequestStatus::Error(BACnetErr::Rejected {
-                code: reject_reason,
-            }),
-        ));
+// Lock the global target addresses. Nothing is left half-updated while the lock is held, so if
+// some thread panicked with it, we simply carry on.
+fn target_addresses() -> MutexGuard<'static, HashMap<DeviceId, TargetDevice>> {
+    TARGET_ADDRESSES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn pending_binds() -> MutexGuard<'static, HashMap<DeviceId, Option<Segmentation>>> {
+    PENDING_BINDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The reason to reject a confirmed request whose service parameters can't be decoded
+fn reject_reason(service_len: u16) -> u8 {
+    let reason = if service_len == 0 {
+        bacnet_sys::BACNET_REJECT_REASON_REJECT_REASON_MISSING_REQUIRED_PARAMETER
+    } else {
+        bacnet_sys::BACNET_REJECT_REASON_REJECT_REASON_INVALID_TAG
+    };
+    reason as u8
+}
+
+// Run the body of a handler called from the C stack. Unwinding into C is undefined behaviour (or
+// an abort), so a panic is logged instead, and the request it belongs to fails with
+// `Error::NoResult`.
+fn catch_panic<F: FnOnce()>(handler: &str, f: F) {
+    if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+        error!("{} panicked", handler);
     }
 }
 
+// The MAC address, network number and (remote) address of the sender of a request
+fn source_address(src: *const bacnet_sys::BACNET_ADDRESS) -> ([u8; 6], u16, [u8; 6]) {
+    let src = unsafe { &*src };
+    let mac_len = min(src.mac_len as usize, src.mac.len());
+    let mut mac_addr = [0u8; 6];
+    mac_addr[..mac_len].copy_from_slice(&src.mac[..mac_len]);
+
+    let mut addr = [0u8; 6];
+    if src.net > 0 {
+        let adr_len = min(src.len as usize, src.adr.len());
+        addr[..adr_len].copy_from_slice(&src.adr[..adr_len]);
+    }
+    (mac_addr, src.net, addr)
+}
+
 fn cstr(ptr: *const c_char) -> String {
     unsafe { CStr::from_ptr(ptr) }
         .to_string_lossy()
@@ -909,14 +1686,38 @@ fn find_matching_device<'a>(
 }
 
 unsafe fn init_service_handlers() {
-    bacnet_sys::Device_Init(std::ptr::null_mut());
+    bacnet_sys::Device_Init(server::object_table());
     bacnet_sys::apdu_set_unconfirmed_handler(
         bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_WHO_IS,
         Some(bacnet_sys::handler_who_is),
     );
     bacnet_sys::apdu_set_unconfirmed_handler(
         bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_I_AM,
-        Some(bacnet_sys::handler_i_am_bind),
+        Some(i_am_bind_handler),
+    );
+    bacnet_sys::apdu_set_unconfirmed_handler(
+        bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_TIME_SYNCHRONIZATION,
+        Some(timesync::time_sync_handler),
+    );
+    bacnet_sys::apdu_set_unconfirmed_handler(
+        bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_UTC_TIME_SYNCHRONIZATION,
+        Some(timesync::utc_time_sync_handler),
+    );
+    bacnet_sys::apdu_set_unconfirmed_handler(
+        bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_EVENT_NOTIFICATION,
+        Some(event::unconfirmed_event_notification_handler),
+    );
+    bacnet_sys::apdu_set_confirmed_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_EVENT_NOTIFICATION,
+        Some(event::confirmed_event_notification_handler),
+    );
+    bacnet_sys::apdu_set_unconfirmed_handler(
+        bacnet_sys::BACNET_UNCONFIRMED_SERVICE_SERVICE_UNCONFIRMED_PRIVATE_TRANSFER,
+        Some(ptransfer::unconfirmed_private_transfer_handler),
+    );
+    bacnet_sys::apdu_set_confirmed_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_PRIVATE_TRANSFER,
+        Some(ptransfer::confirmed_private_transfer_handler),
     );
     bacnet_sys::apdu_set_unrecognized_service_handler_handler(Some(
         bacnet_sys::handler_unrecognized_service,
@@ -934,6 +1735,79 @@ unsafe fn init_service_handlers() {
         bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_READ_PROPERTY,
         Some(my_error_handler),
     );
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_READ_PROP_MULTIPLE,
+        Some(rpm::read_property_multiple_ack_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_READ_PROP_MULTIPLE,
+        Some(my_error_handler),
+    );
+
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ATOMIC_READ_FILE,
+        Some(file::read_file_ack_handler),
+    );
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ATOMIC_WRITE_FILE,
+        Some(file::write_file_ack_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ATOMIC_READ_FILE,
+        Some(my_error_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ATOMIC_WRITE_FILE,
+        Some(my_error_handler),
+    );
+
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_GET_EVENT_INFORMATION,
+        Some(alarm::get_event_ack_handler),
+    );
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_GET_ALARM_SUMMARY,
+        Some(alarm::get_alarm_summary_ack_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_GET_EVENT_INFORMATION,
+        Some(my_error_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_GET_ALARM_SUMMARY,
+        Some(my_error_handler),
+    );
+
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_PRIVATE_TRANSFER,
+        Some(ptransfer::private_transfer_ack_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_PRIVATE_TRANSFER,
+        Some(my_error_handler),
+    );
+
+    bacnet_sys::apdu_set_confirmed_ack_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_CREATE_OBJECT,
+        Some(object::create_object_ack_handler),
+    );
+    bacnet_sys::apdu_set_error_handler(
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_CREATE_OBJECT,
+        Some(my_error_handler),
+    );
+
+    // Services answered with a SimpleACK
+    for service in &[
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_DEVICE_COMMUNICATION_CONTROL,
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_REINITIALIZE_DEVICE,
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ACKNOWLEDGE_ALARM,
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_DELETE_OBJECT,
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ADD_LIST_ELEMENT,
+        bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_REMOVE_LIST_ELEMENT,
+    ] {
+        bacnet_sys::apdu_set_confirmed_simple_ack_handler(*service, Some(my_simple_ack_handler));
+        bacnet_sys::apdu_set_error_handler(*service, Some(my_error_handler));
+    }
     bacnet_sys::apdu_set_abort_handler(Some(my_abort_handler));
     bacnet_sys::apdu_set_reject_handler(Some(my_reject_handler));
 }
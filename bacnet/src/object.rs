@@ -0,0 +1,400 @@
+//! CreateObject, DeleteObject, AddListElement and RemoveListElement
+//!
+//! The stack doesn't have encoders for these services, so the requests are encoded here. Their
+//! error PDUs (CreateObject-Error and ChangeList-Error) carry the number of the first element that
+//! failed, which the stack's error handling doesn't know about, so they're also decoded here.
+
+use crate::value::BACnetValue;
+use crate::{
+    bacnet_error, decode_values, find_matching_device, send_confirmed_request, target_addresses,
+    Ack, BACnetDevice, Error, RequestStatus, Result,
+};
+
+/// The object to create with CreateObject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectSpecifier {
+    /// Create an object of the given type, the device picks the instance number
+    Type(bacnet_sys::BACNET_OBJECT_TYPE),
+    /// Create an object with the given object identifier
+    Identifier {
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+    },
+}
+
+/// A property value, e.g. one of the initial values of CreateObject
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyValue {
+    pub property: bacnet_sys::BACNET_PROPERTY_ID,
+    pub array_index: Option<u32>,
+    /// An Array is encoded as a sequence of values, i.e. as the whole array or list.
+    pub value: BACnetValue,
+    pub priority: Option<u8>,
+}
+
+impl PropertyValue {
+    pub fn new(property: bacnet_sys::BACNET_PROPERTY_ID, value: BACnetValue) -> Self {
+        PropertyValue {
+            property,
+            array_index: None,
+            value,
+            priority: None,
+        }
+    }
+}
+
+impl BACnetDevice {
+    /// Create an object with CreateObject, returning the object type and instance of the new
+    /// object.
+    ///
+    /// If one of the initial values can't be written, the error is
+    /// `Error::FailedElement` with the (1-based) number of that value.
+    pub fn create_object(
+        &self,
+        object: ObjectSpecifier,
+        initial_values: &[PropertyValue],
+    ) -> Result<(bacnet_sys::BACNET_OBJECT_TYPE, u32)> {
+        let mut request = Vec::new();
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_opening_tag(apdu, 0)
+        });
+        match object {
+            ObjectSpecifier::Type(object_type) => push(&mut request, |apdu| unsafe {
+                bacnet_sys::encode_context_enumerated(apdu, 0, object_type)
+            }),
+            ObjectSpecifier::Identifier {
+                object_type,
+                object_instance,
+            } => push(&mut request, |apdu| unsafe {
+                bacnet_sys::encode_context_object_id(apdu, 1, object_type, object_instance)
+            }),
+        }
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_closing_tag(apdu, 0)
+        });
+        if !initial_values.is_empty() {
+            push(&mut request, |apdu| unsafe {
+                bacnet_sys::encode_opening_tag(apdu, 1)
+            });
+            for value in initial_values {
+                encode_property_value(&mut request, value)?;
+            }
+            push(&mut request, |apdu| unsafe {
+                bacnet_sys::encode_closing_tag(apdu, 1)
+            });
+        }
+
+        let ack = self.confirmed_request(|| {
+            self.send_request(
+                bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_CREATE_OBJECT,
+                &request,
+            )
+        })?;
+        match ack {
+            Some(Ack::Value(BACnetValue::ObjectId {
+                object_type,
+                object_instance,
+            })) => Ok((object_type, object_instance)),
+            _ => Err(Error::NoValueWasExtracted),
+        }
+    }
+
+    /// Delete an object with DeleteObject
+    pub fn delete_object(
+        &self,
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+    ) -> Result<()> {
+        let mut request = Vec::new();
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_application_object_id(apdu, object_type, object_instance)
+        });
+        self.confirmed_request(|| {
+            self.send_request(
+                bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_DELETE_OBJECT,
+                &request,
+            )
+        })
+        .map(|_| ())
+    }
+
+    /// Add elements to a list property with AddListElement. Elements that are already in the list
+    /// are left alone.
+    ///
+    /// If one of the elements can't be added, the error is `Error::FailedElement` with the
+    /// (1-based) number of that element.
+    pub fn add_list_element(
+        &self,
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+        property: bacnet_sys::BACNET_PROPERTY_ID,
+        array_index: Option<u32>,
+        elements: &[BACnetValue],
+    ) -> Result<()> {
+        self.change_list(
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ADD_LIST_ELEMENT,
+            object_type,
+            object_instance,
+            property,
+            array_index,
+            elements,
+        )
+    }
+
+    /// Remove elements from a list property with RemoveListElement
+    ///
+    /// If one of the elements isn't in the list, the error is `Error::FailedElement` with the
+    /// (1-based) number of that element.
+    pub fn remove_list_element(
+        &self,
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+        property: bacnet_sys::BACNET_PROPERTY_ID,
+        array_index: Option<u32>,
+        elements: &[BACnetValue],
+    ) -> Result<()> {
+        self.change_list(
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_REMOVE_LIST_ELEMENT,
+            object_type,
+            object_instance,
+            property,
+            array_index,
+            elements,
+        )
+    }
+
+    fn change_list(
+        &self,
+        service: bacnet_sys::BACNET_CONFIRMED_SERVICE,
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+        property: bacnet_sys::BACNET_PROPERTY_ID,
+        array_index: Option<u32>,
+        elements: &[BACnetValue],
+    ) -> Result<()> {
+        let mut request = Vec::new();
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_context_object_id(apdu, 0, object_type, object_instance)
+        });
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_context_enumerated(apdu, 1, property)
+        });
+        if let Some(index) = array_index {
+            push(&mut request, |apdu| unsafe {
+                bacnet_sys::encode_context_unsigned(apdu, 2, index.into())
+            });
+        }
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_opening_tag(apdu, 3)
+        });
+        for element in elements {
+            element.encode(&mut request)?;
+        }
+        push(&mut request, |apdu| unsafe {
+            bacnet_sys::encode_closing_tag(apdu, 3)
+        });
+
+        self.confirmed_request(|| self.send_request(service, &request))
+            .map(|_| ())
+    }
+
+    // Send a confirmed request with the given (already encoded) service request
+    fn send_request(&self, service: bacnet_sys::BACNET_CONFIRMED_SERVICE, request: &[u8]) -> u8 {
+        send_confirmed_request(self.device_id, |apdu, invoke_id| {
+            if request.len() + 4 > apdu.len() {
+                return 0;
+            }
+            apdu[0] = bacnet_sys::BACNET_PDU_TYPE_PDU_TYPE_CONFIRMED_SERVICE_REQUEST as u8;
+            apdu[1] =
+                unsafe { bacnet_sys::encode_max_segs_max_apdu(0, bacnet_sys::MAX_APDU as i32) };
+            apdu[2] = invoke_id;
+            apdu[3] = service as u8;
+            apdu[4..4 + request.len()].copy_from_slice(request);
+            (4 + request.len()) as i32
+        })
+    }
+}
+
+// Append the output of one of the stack's encoding functions
+fn push<F>(buf: &mut Vec<u8>, encode: F)
+where
+    F: FnOnce(*mut u8) -> i32,
+{
+    let mut scratch = [0u8; 16];
+    let len = encode(scratch.as_mut_ptr());
+    buf.extend_from_slice(&scratch[..len as usize]);
+}
+
+// Encode a BACnetPropertyValue
+fn encode_property_value(buf: &mut Vec<u8>, value: &PropertyValue) -> Result<()> {
+    push(buf, |apdu| unsafe {
+        bacnet_sys::encode_context_enumerated(apdu, 0, value.property)
+    });
+    if let Some(index) = value.array_index {
+        push(buf, |apdu| unsafe {
+            bacnet_sys::encode_context_unsigned(apdu, 1, index.into())
+        });
+    }
+    push(buf, |apdu| unsafe {
+        bacnet_sys::encode_opening_tag(apdu, 2)
+    });
+    value.value.encode(buf)?;
+    push(buf, |apdu| unsafe {
+        bacnet_sys::encode_closing_tag(apdu, 2)
+    });
+    if let Some(priority) = value.priority {
+        push(buf, |apdu| unsafe {
+            bacnet_sys::encode_context_unsigned(apdu, 3, priority.into())
+        });
+    }
+    Ok(())
+}
+
+// Handle CreateObject-Error and ChangeList-Error PDUs, which the stack would misinterpret. Returns
+// true if the PDU was handled.
+pub(crate) fn handle_error_pdu(src: &bacnet_sys::BACNET_ADDRESS, pdu: &mut [u8]) -> bool {
+    let mut src = *src;
+    let mut dest = bacnet_sys::BACNET_ADDRESS::default();
+    let mut npdu_data = bacnet_sys::BACNET_NPDU_DATA::default();
+    if pdu.is_empty() {
+        return false;
+    }
+    let offset =
+        unsafe { bacnet_sys::npdu_decode(pdu.as_mut_ptr(), &mut dest, &mut src, &mut npdu_data) };
+    if offset <= 0 || npdu_data.network_layer_message {
+        return false;
+    }
+    let apdu = &mut pdu[offset as usize..];
+    if apdu.len() < 5
+        || u32::from(apdu[0] & 0xf0) != bacnet_sys::BACNET_PDU_TYPE_PDU_TYPE_ERROR
+        || ![
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_CREATE_OBJECT,
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_ADD_LIST_ELEMENT,
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_REMOVE_LIST_ELEMENT,
+        ]
+        .contains(&u32::from(apdu[2]))
+    {
+        return false;
+    }
+    let invoke_id = apdu[1];
+    let (class, code, first_failed_element) = match decode_element_error(&mut apdu[3..]) {
+        Some(decoded) => decoded,
+        None => return false,
+    };
+
+    let mut lock = target_addresses();
+    if let Some(target) = find_matching_device(&mut lock, &mut src, invoke_id) {
+        target.ack = Some(Err(Error::FailedElement {
+            error: bacnet_error(class, code),
+            first_failed_element,
+        }));
+        target.request = Some((invoke_id, RequestStatus::Done));
+    }
+    drop(lock);
+    unsafe { bacnet_sys::tsm_free_invoke_id(invoke_id) };
+    true
+}
+
+// Decode
+//
+//   SEQUENCE {
+//       errorType                [0] Error,
+//       firstFailedElementNumber [1] Unsigned
+//   }
+fn decode_element_error(data: &mut [u8]) -> Option<(u32, u32, u64)> {
+    // The shortest possible encoding
+    if data.len() < 8 {
+        return None;
+    }
+    if !unsafe { bacnet_sys::decode_is_opening_tag_number(data.as_mut_ptr(), 0) } {
+        return None;
+    }
+    let mut len = 1;
+    let mut enumerated = || {
+        let mut tag_number = 0;
+        let mut len_value = 0;
+        if len >= data.len() {
+            return None;
+        }
+        let tag_len = unsafe {
+            bacnet_sys::bacnet_tag_number_and_value_decode(
+                data[len..].as_mut_ptr(),
+                (data.len() - len) as u32,
+                &mut tag_number,
+                &mut len_value,
+            )
+        };
+        if tag_len <= 0 {
+            return None;
+        }
+        len += tag_len as usize;
+        if u32::from(tag_number)
+            != bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_ENUMERATED
+            || len_value > 4
+            || len + len_value as usize > data.len()
+        {
+            return None;
+        }
+        let mut value = 0;
+        let value_len = unsafe {
+            bacnet_sys::bacnet_enumerated_decode(
+                data[len..].as_mut_ptr(),
+                (data.len() - len) as u16,
+                len_value,
+                &mut value,
+            )
+        };
+        if value_len <= 0 {
+            return None;
+        }
+        len += value_len as usize;
+        Some(value)
+    };
+    let class = enumerated()?;
+    let code = enumerated()?;
+    if len >= data.len()
+        || !unsafe { bacnet_sys::decode_is_closing_tag_number(data[len..].as_mut_ptr(), 0) }
+    {
+        return None;
+    }
+    len += 1;
+    if len >= data.len() {
+        return None;
+    }
+    let mut first_failed_element = 0;
+    if unsafe {
+        bacnet_sys::bacnet_unsigned_context_decode(
+            data[len..].as_mut_ptr(),
+            (data.len() - len) as u16,
+            1,
+            &mut first_failed_element,
+        )
+    } <= 0
+    {
+        return None;
+    }
+    Some((class, code, first_failed_element))
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn create_object_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("create_object_ack_handler", || {
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let data =
+                unsafe { std::slice::from_raw_parts_mut(service_request, service_len.into()) };
+            let value = decode_values(data);
+            if value.is_err() {
+                error!("<decode failed>");
+            }
+            target.ack = Some(value.map(Ack::Value));
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
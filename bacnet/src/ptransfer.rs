@@ -0,0 +1,362 @@
+//! ConfirmedPrivateTransfer and UnconfirmedPrivateTransfer
+//!
+//! Private transfers to other devices are sent with `BACnetDevice::private_transfer()`.
+//!
+//! Incoming private transfers are dispatched to the handler registered for their vendor ID and
+//! service number with `register_handler()`. For ConfirmedPrivateTransfer the value returned by the
+//! handler is sent back as the result block, or as a ConfirmedPrivateTransfer-Error if the handler
+//! fails. Confirmed transfers without a handler are answered with an
+//! optional-functionality-not-supported error, unconfirmed ones are ignored.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::value::BACnetValue;
+use crate::{
+    decode_values, find_matching_device, send_confirmed_request, source_address, target_addresses,
+    Ack, BACnetDevice, Error, ErrorClass, ErrorCode, RequestStatus, Result,
+};
+
+type Handler =
+    Arc<dyn Fn(&PrivateTransfer) -> std::result::Result<BACnetValue, TransferError> + Send + Sync>;
+
+lazy_static! {
+    /// Handlers for incoming private transfers, keyed by vendor ID and service number
+    static ref HANDLERS: Mutex<HashMap<(u16, u32), Handler>> = Mutex::new(HashMap::new());
+}
+
+/// A private transfer received from the network
+#[derive(Debug, Clone)]
+pub struct PrivateTransfer {
+    /// `true` for ConfirmedPrivateTransfer, `false` for UnconfirmedPrivateTransfer
+    pub confirmed: bool,
+    pub vendor_id: u16,
+    pub service_number: u32,
+    /// The service parameters. Null if there are none, an Array if there's more than one value.
+    pub parameters: BACnetValue,
+    pub mac_addr: [u8; 6],
+    pub network_number: u16,
+    pub addr: [u8; 6],
+}
+
+/// The error class and code to answer a ConfirmedPrivateTransfer with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferError {
+    pub class: ErrorClass,
+    pub code: ErrorCode,
+}
+
+/// Handle incoming private transfers for the given vendor ID and service number, replacing any
+/// earlier handler for them.
+///
+/// The handler runs while the stack is driven forward (e.g. by `bacnet::poll()`), so it shouldn't
+/// block for long.
+pub fn register_handler<F>(vendor_id: u16, service_number: u32, handler: F)
+where
+    F: Fn(&PrivateTransfer) -> std::result::Result<BACnetValue, TransferError>
+        + Send
+        + Sync
+        + 'static,
+{
+    crate::init_stack();
+    if let Ok(mut handlers) = HANDLERS.lock() {
+        handlers.insert((vendor_id, service_number), Arc::new(handler));
+    }
+}
+
+/// Stop handling private transfers for the given vendor ID and service number
+pub fn unregister_handler(vendor_id: u16, service_number: u32) {
+    if let Ok(mut handlers) = HANDLERS.lock() {
+        handlers.remove(&(vendor_id, service_number));
+    }
+}
+
+impl BACnetDevice {
+    /// Invoke a proprietary service with ConfirmedPrivateTransfer, returning the result block of
+    /// the reply (Null if there is none).
+    ///
+    /// An Array of `parameters` is sent as a sequence of values.
+    pub fn private_transfer(
+        &self,
+        vendor_id: u16,
+        service_number: u32,
+        parameters: BACnetValue,
+    ) -> Result<BACnetValue> {
+        let mut encoded = Vec::new();
+        parameters.encode(&mut encoded)?;
+        let mut data = bacnet_sys::BACNET_PRIVATE_TRANSFER_DATA {
+            vendorID: vendor_id,
+            serviceNumber: service_number,
+            serviceParameters: encoded.as_mut_ptr(),
+            serviceParametersLen: encoded.len() as i32,
+        };
+
+        let ack = self.confirmed_request(|| {
+            send_confirmed_request(self.device_id, |apdu, invoke_id| {
+                // The stack doesn't check that the parameters fit
+                if encoded.len() + 16 > apdu.len() {
+                    return 0;
+                }
+                unsafe {
+                    bacnet_sys::ptransfer_encode_apdu(apdu.as_mut_ptr(), invoke_id, &mut data)
+                }
+            })
+        })?;
+        match ack {
+            Some(Ack::PrivateTransfer(result)) => Ok(result),
+            _ => Err(Error::NoValueWasExtracted),
+        }
+    }
+
+    /// Invoke a proprietary service with UnconfirmedPrivateTransfer
+    pub fn unconfirmed_private_transfer(
+        &self,
+        vendor_id: u16,
+        service_number: u32,
+        parameters: BACnetValue,
+    ) -> Result<()> {
+        let mut encoded = Vec::new();
+        parameters.encode(&mut encoded)?;
+        if encoded.len() + 16 > bacnet_sys::MAX_APDU as usize {
+            return Err(Error::FailedToSendRequest);
+        }
+        let mut data = bacnet_sys::BACNET_PRIVATE_TRANSFER_DATA {
+            vendorID: vendor_id,
+            serviceNumber: service_number,
+            serviceParameters: encoded.as_mut_ptr(),
+            serviceParametersLen: encoded.len() as i32,
+        };
+
+        let mut dest = {
+            let lock = target_addresses();
+            match lock.get(&self.device_id) {
+                Some(target) => target.addr,
+                None => {
+                    return Err(Error::NotConnectedToDevice {
+                        device_id: self.device_id,
+                    })
+                }
+            }
+        };
+        let sent = unsafe { bacnet_sys::Send_UnconfirmedPrivateTransfer(&mut dest, &mut data) };
+        if sent <= 0 {
+            return Err(Error::FailedToSendRequest);
+        }
+        Ok(())
+    }
+}
+
+// Decode a private transfer request or ACK, which share the same encoding. Returns the vendor ID,
+// service number and the decoded parameters/result block.
+fn decode_transfer(service_request: *mut u8, service_len: u16) -> Result<(u16, u32, BACnetValue)> {
+    // The stack hands over no buffer at all for a request without parameters
+    if service_request.is_null() {
+        return Err(Error::FailedToDecodeData);
+    }
+    let mut request =
+        unsafe { std::slice::from_raw_parts(service_request, service_len.into()) }.to_vec();
+    let mut data = bacnet_sys::BACNET_PRIVATE_TRANSFER_DATA::default();
+    let mut len = unsafe {
+        bacnet_sys::ptransfer_decode_service_request(
+            request.as_mut_ptr(),
+            request.len() as u32,
+            &mut data,
+        )
+    };
+    if len < 0 {
+        // The parameters are optional, but the stack insists on them. Retry with an empty
+        // parameter block.
+        request.extend_from_slice(&[0x2e, 0x2f]);
+        len = unsafe {
+            bacnet_sys::ptransfer_decode_service_request(
+                request.as_mut_ptr(),
+                request.len() as u32,
+                &mut data,
+            )
+        };
+    }
+    if len < 0 || data.serviceParametersLen < 0 {
+        return Err(Error::FailedToDecodeData);
+    }
+    let parameters = unsafe {
+        std::slice::from_raw_parts_mut(data.serviceParameters, data.serviceParametersLen as usize)
+    };
+    let parameters = decode_values(parameters)?;
+    Ok((data.vendorID, data.serviceNumber, parameters))
+}
+
+fn handle_transfer(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    confirmed: bool,
+) -> Option<(PrivateTransfer, Option<Handler>)> {
+    let (vendor_id, service_number, parameters) =
+        match decode_transfer(service_request, service_len) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                error!("unable to decode private transfer: {}", err);
+                return None;
+            }
+        };
+    let (mac_addr, network_number, addr) = source_address(src);
+    let transfer = PrivateTransfer {
+        confirmed,
+        vendor_id,
+        service_number,
+        parameters,
+        mac_addr,
+        network_number,
+        addr,
+    };
+    debug!("private transfer {:?}", transfer);
+
+    // Don't hold on to the lock while running the handler, it might want to (un)register handlers
+    let handler = HANDLERS
+        .lock()
+        .ok()
+        .and_then(|handlers| handlers.get(&(vendor_id, service_number)).cloned());
+    Some((transfer, handler))
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn private_transfer_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("private_transfer_ack_handler", || {
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let result = decode_transfer(service_request, service_len);
+            if let Err(err) = &result {
+                error!("<decode failed> {}", err);
+            }
+            target.ack = Some(result.map(|(_, _, value)| Ack::PrivateTransfer(value)));
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn unconfirmed_private_transfer_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+) {
+    crate::catch_panic("unconfirmed_private_transfer_handler", || {
+        if let Some((transfer, Some(handler))) =
+            handle_transfer(service_request, service_len, src, false)
+        {
+            if let Err(err) = handler(&transfer) {
+                debug!("unconfirmed private transfer failed: {:?}", err);
+            }
+        }
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn confirmed_private_transfer_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_DATA,
+) {
+    crate::catch_panic("confirmed_private_transfer_handler", || {
+        let (invoke_id, segmented) =
+            unsafe { ((*service_data).invoke_id, (*service_data).segmented_message) };
+        let mut buf = [0u8; bacnet_sys::MAX_PDU as usize];
+        let mut my_address = bacnet_sys::BACNET_ADDRESS::default();
+        let mut npdu_data = bacnet_sys::BACNET_NPDU_DATA::default();
+
+        unsafe {
+            bacnet_sys::bip_get_my_address(&mut my_address);
+            bacnet_sys::npdu_encode_npdu_data(
+                &mut npdu_data,
+                false,
+                bacnet_sys::BACNET_MESSAGE_PRIORITY_MESSAGE_PRIORITY_NORMAL,
+            );
+        }
+        let npdu_len = unsafe {
+            bacnet_sys::npdu_encode_pdu(buf.as_mut_ptr(), src, &mut my_address, &mut npdu_data)
+        } as usize;
+        let apdu = buf[npdu_len..].as_mut_ptr();
+
+        let transfer = if segmented {
+            None
+        } else {
+            handle_transfer(service_request, service_len, src, true)
+        };
+        let len = match transfer {
+            None if segmented => unsafe {
+                bacnet_sys::abort_encode_apdu(
+                    apdu,
+                    invoke_id,
+                    bacnet_sys::BACNET_ABORT_REASON_ABORT_REASON_SEGMENTATION_NOT_SUPPORTED as u8,
+                    true,
+                )
+            },
+            None => unsafe {
+                bacnet_sys::reject_encode_apdu(apdu, invoke_id, crate::reject_reason(service_len))
+            },
+            Some((transfer, handler)) => {
+                let result = match handler {
+                    Some(handler) => handler(&transfer),
+                    None => Err(TransferError {
+                        class: ErrorClass::Services,
+                        code: ErrorCode::OptionalFunctionalityNotSupported,
+                    }),
+                };
+                let mut data = bacnet_sys::BACNET_PRIVATE_TRANSFER_DATA {
+                    vendorID: transfer.vendor_id,
+                    serviceNumber: transfer.service_number,
+                    ..Default::default()
+                };
+                let mut encoded = Vec::new();
+                let result = result.and_then(|value| {
+                    value.encode(&mut encoded).map_err(|_| TransferError {
+                        class: ErrorClass::Services,
+                        code: ErrorCode::Other,
+                    })
+                });
+                // Leave room for the NPDU and the rest of the ACK
+                let result = match result {
+                    Ok(()) if npdu_len + encoded.len() + 16 > bacnet_sys::MAX_APDU as usize => {
+                        Err(TransferError {
+                            class: ErrorClass::Services,
+                            code: ErrorCode::Other,
+                        })
+                    }
+                    result => result,
+                };
+                match result {
+                    Ok(()) => unsafe {
+                        data.serviceParameters = encoded.as_mut_ptr();
+                        data.serviceParametersLen = encoded.len() as i32;
+                        bacnet_sys::ptransfer_ack_encode_apdu(apdu, invoke_id, &mut data)
+                    },
+                    Err(err) => unsafe {
+                        bacnet_sys::ptransfer_error_encode_apdu(
+                            apdu,
+                            invoke_id,
+                            err.class.to_u32(),
+                            err.code.to_u32(),
+                            &mut data,
+                        )
+                    },
+                }
+            }
+        };
+
+        unsafe {
+            bacnet_sys::bip_send_pdu(
+                src,
+                &mut npdu_data,
+                buf.as_mut_ptr(),
+                (npdu_len as i32 + len) as u32,
+            );
+        }
+    });
+}
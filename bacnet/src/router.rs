@@ -0,0 +1,277 @@
+//! Router discovery (Who-Is-Router-To-Network / I-Am-Router-To-Network)
+//!
+//! The network layer messages routers send us are collected in a global `NetworkMap`, listing
+//! each reachable network (DNET), the router serving it, and whether the router last reported it
+//! as busy or rejected a message to it. Since the stack only hands network layer messages to us
+//! while it is driven forward, the map is updated by `WhoIsRouter::execute()`, `bacnet::poll()`
+//! and on-going requests alike.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// All networks we have learned about so far. Updated by handle_network_message().
+    static ref NETWORK_MAP: Mutex<NetworkMap> = Mutex::new(NetworkMap::default());
+}
+
+/// The networks reachable through routers, keyed by network number.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkMap {
+    routes: BTreeMap<u16, Route>,
+}
+
+impl NetworkMap {
+    /// The route to the given network, if it is known.
+    pub fn get(&self, dnet: u16) -> Option<&Route> {
+        self.routes.get(&dnet)
+    }
+
+    /// All known routes, ordered by network number.
+    pub fn routes(&self) -> impl Iterator<Item = &Route> {
+        self.routes.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    fn update<F>(&mut self, dnet: u16, router: &bacnet_sys::BACNET_ADDRESS, status: F)
+    where
+        F: FnOnce(&mut Route),
+    {
+        let (mac_addr, network_number, addr) = crate::source_address(router);
+        let route = self.routes.entry(dnet).or_insert(Route {
+            dnet,
+            mac_addr,
+            network_number,
+            addr,
+            status: RouteStatus::Available,
+        });
+        route.mac_addr = mac_addr;
+        route.network_number = network_number;
+        route.addr = addr;
+        status(route);
+    }
+
+    // Set the status of all networks served by the given router
+    fn update_router(&mut self, router: &bacnet_sys::BACNET_ADDRESS, status: RouteStatus) {
+        let (mac_addr, network_number, addr) = crate::source_address(router);
+        for route in self.routes.values_mut() {
+            if route.mac_addr == mac_addr
+                && route.network_number == network_number
+                && route.addr == addr
+            {
+                route.status = status;
+            }
+        }
+    }
+}
+
+/// A network reachable through a router.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// The network number (DNET)
+    pub dnet: u16,
+    /// The MAC address of the router serving the network
+    pub mac_addr: [u8; 6],
+    /// The network the router itself is on, 0 if it's on our local network
+    pub network_number: u16,
+    /// The address of the router on `network_number`, if it's not on our local network
+    pub addr: [u8; 6],
+    pub status: RouteStatus,
+}
+
+/// What the router last told us about a network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteStatus {
+    /// Announced by I-Am-Router-To-Network or Router-Available-To-Network
+    Available,
+    /// The router sent Router-Busy-To-Network, and won't accept messages to the network until it
+    /// sends Router-Available-To-Network.
+    Busy,
+    /// The router answered a message to the network with Reject-Message-To-Network.
+    Rejected(RejectReason),
+}
+
+/// The reason given in a Reject-Message-To-Network message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    Other,
+    /// The router doesn't know a route to the network
+    NoRoute,
+    RouterBusy,
+    UnknownMessageType,
+    MessageTooLong,
+    SecurityError,
+    AddressingError,
+    Unknown(u8),
+}
+
+impl RejectReason {
+    fn from_sys(reason: u8) -> Self {
+        match u32::from(reason) {
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_UNKNOWN_ERROR => Self::Other,
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_NO_ROUTE => Self::NoRoute,
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_ROUTER_BUSY => {
+                Self::RouterBusy
+            }
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_UNKNOWN_MESSAGE_TYPE => {
+                Self::UnknownMessageType
+            }
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_MESSAGE_TOO_LONG => {
+                Self::MessageTooLong
+            }
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_BACNET_SECURITY => {
+                Self::SecurityError
+            }
+            bacnet_sys::BACNET_NETWORK_REJECT_REASONS_NETWORK_REJECT_BAD_ADDRESS => {
+                Self::AddressingError
+            }
+            _ => Self::Unknown(reason),
+        }
+    }
+}
+
+/// A snapshot of the networks learned about so far.
+pub fn network_map() -> NetworkMap {
+    NETWORK_MAP
+        .lock()
+        .map(|lock| lock.clone())
+        .unwrap_or_default()
+}
+
+/// Forget all networks learned about so far.
+pub fn clear_network_map() {
+    if let Ok(mut lock) = NETWORK_MAP.lock() {
+        lock.routes.clear();
+    }
+}
+
+/// A Who-Is-Router-To-Network query, broadcast on the local network.
+pub struct WhoIsRouter {
+    /// How long to wait for I-Am-Router-To-Network answers
+    timeout: Duration,
+
+    /// Only ask for routers to the given network, default is `None` which means all networks.
+    network: Option<u16>,
+}
+
+// WhoIsRouter::new().network(5).execute()
+impl WhoIsRouter {
+    pub fn new() -> WhoIsRouter {
+        WhoIsRouter::default()
+    }
+
+    /// Set the amount of time to wait for I-Am-Router-To-Network answers. Default: 3s
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn network<N>(mut self, network: N) -> Self
+    where
+        N: Into<Option<u16>>,
+    {
+        self.network = network.into();
+        self
+    }
+
+    /// Send the query and wait for answers. Returns the network map, including networks learned
+    /// about earlier.
+    pub fn execute(self) -> NetworkMap {
+        crate::init_stack();
+
+        let mut dest = bacnet_sys::BACNET_ADDRESS::default();
+        unsafe {
+            bacnet_sys::bip_get_broadcast_address(&mut dest);
+            bacnet_sys::Send_Who_Is_Router_To_Network(
+                &mut dest,
+                self.network.map(i32::from).unwrap_or(-1),
+            );
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < self.timeout {
+            crate::receive(100);
+        }
+        network_map()
+    }
+}
+
+impl Default for WhoIsRouter {
+    fn default() -> Self {
+        WhoIsRouter {
+            timeout: Duration::from_secs(3),
+            network: None,
+        }
+    }
+}
+
+// Handle network layer messages from routers, which the stack's npdu_handler() discards. Returns
+// true if the PDU was a network layer message.
+pub(crate) fn handle_network_message(src: &bacnet_sys::BACNET_ADDRESS, pdu: &mut [u8]) -> bool {
+    let mut src = *src;
+    let mut dest = bacnet_sys::BACNET_ADDRESS::default();
+    let mut npdu_data = bacnet_sys::BACNET_NPDU_DATA::default();
+    if pdu.is_empty() {
+        return false;
+    }
+    let offset =
+        unsafe { bacnet_sys::npdu_decode(pdu.as_mut_ptr(), &mut dest, &mut src, &mut npdu_data) };
+    if offset <= 0 || !npdu_data.network_layer_message {
+        return false;
+    }
+    let payload = &pdu[offset as usize..];
+    let dnets = || {
+        payload
+            .chunks_exact(2)
+            .map(|n| u16::from_be_bytes([n[0], n[1]]))
+    };
+
+    let mut map = match NETWORK_MAP.lock() {
+        Ok(lock) => lock,
+        Err(_) => return true,
+    };
+    match npdu_data.network_message_type {
+        bacnet_sys::BACNET_NETWORK_MESSAGE_TYPE_NETWORK_MESSAGE_I_AM_ROUTER_TO_NETWORK => {
+            for dnet in dnets() {
+                debug!("I-Am-Router-To-Network {} from {:02X?}", dnet, src.mac);
+                map.update(dnet, &src, |route| route.status = RouteStatus::Available);
+            }
+        }
+        bacnet_sys::BACNET_NETWORK_MESSAGE_TYPE_NETWORK_MESSAGE_ROUTER_BUSY_TO_NETWORK
+        | bacnet_sys::BACNET_NETWORK_MESSAGE_TYPE_NETWORK_MESSAGE_ROUTER_AVAILABLE_TO_NETWORK => {
+            let status = if npdu_data.network_message_type
+                == bacnet_sys::BACNET_NETWORK_MESSAGE_TYPE_NETWORK_MESSAGE_ROUTER_BUSY_TO_NETWORK
+            {
+                RouteStatus::Busy
+            } else {
+                RouteStatus::Available
+            };
+            // An empty list means all networks served by the router
+            if payload.len() < 2 {
+                map.update_router(&src, status);
+            }
+            for dnet in dnets() {
+                map.update(dnet, &src, |route| route.status = status);
+            }
+        }
+        bacnet_sys::BACNET_NETWORK_MESSAGE_TYPE_NETWORK_MESSAGE_REJECT_MESSAGE_TO_NETWORK => {
+            if payload.len() >= 3 {
+                let reason = RejectReason::from_sys(payload[0]);
+                let dnet = u16::from_be_bytes([payload[1], payload[2]]);
+                debug!("Reject-Message-To-Network {}: {:?}", dnet, reason);
+                map.update(dnet, &src, |route| {
+                    route.status = RouteStatus::Rejected(reason)
+                });
+            }
+        }
+        other => debug!("ignoring network layer message {}", other),
+    }
+    true
+}
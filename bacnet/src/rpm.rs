@@ -0,0 +1,553 @@
+//! ReadPropertyMultiple, and reading all properties of an object with as few requests as possible
+//!
+//! `read_all_properties()` first asks for ALL properties in one ReadPropertyMultiple. When the
+//! answer doesn't fit in an unsegmented APDU (or ALL isn't supported), it reads `property-list`
+//! and asks for those properties in chunks, halving a chunk whenever its answer is too large.
+//! Devices that don't do ReadPropertyMultiple at all are remembered, and read one property at a
+//! time.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::epics::ObjectId;
+use crate::value::BACnetValue;
+use crate::{
+    decode_value, find_matching_device, send_confirmed_request, target_addresses, Ack,
+    BACnetDevice, BACnetErr, Error, ErrorClass, ErrorCode, ObjectPropertyId, ObjectType,
+    RejectReason, RequestStatus, Result,
+};
+
+// The number of properties asked for in one request when reading a property list
+const CHUNK_SIZE: usize = 16;
+
+/// The result of reading one property with ReadPropertyMultiple
+#[derive(Debug)]
+pub struct PropertyResult {
+    pub property: ObjectPropertyId,
+    pub array_index: Option<u32>,
+    /// The value, or the error the device answered for this property. Constructed values that
+    /// can't be decoded are `Error::UnhandledTypeTag`.
+    pub value: Result<BACnetValue>,
+}
+
+impl BACnetDevice {
+    /// Read several properties of an object with a single ReadPropertyMultiple request. The
+    /// special properties ALL, REQUIRED and OPTIONAL ask for all properties of that kind.
+    ///
+    /// Errors for individual properties are returned in their `PropertyResult`.
+    pub fn read_prop_multiple(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+        properties: &[ObjectPropertyId],
+    ) -> Result<Vec<PropertyResult>> {
+        let ack = self.confirmed_request(|| {
+            send_confirmed_request(self.device_id, |apdu, invoke_id| unsafe {
+                // Each property takes at most 5 bytes, and the object begin and end 7 more
+                if 4 + 7 + 5 * properties.len() > apdu.len() {
+                    return 0;
+                }
+                let mut len = bacnet_sys::rpm_encode_apdu_init(apdu.as_mut_ptr(), invoke_id);
+                len += bacnet_sys::rpm_encode_apdu_object_begin(
+                    apdu[len as usize..].as_mut_ptr(),
+                    object_type,
+                    object_instance,
+                );
+                for property in properties {
+                    len += bacnet_sys::rpm_encode_apdu_object_property(
+                        apdu[len as usize..].as_mut_ptr(),
+                        *property,
+                        bacnet_sys::BACNET_ARRAY_ALL,
+                    );
+                }
+                len + bacnet_sys::rpm_encode_apdu_object_end(apdu[len as usize..].as_mut_ptr())
+            })
+        });
+        match ack {
+            Ok(Some(Ack::PropertyResults(results))) => {
+                self.set_rpm_supported(true);
+                Ok(results)
+            }
+            Ok(_) => Err(Error::NoValueWasExtracted),
+            Err(Error::BacnetError { error }) if is_unrecognized_service(&error) => {
+                debug!("device {} doesn't support RPM", self.device_id);
+                self.set_rpm_supported(false);
+                Err(Error::BacnetError { error })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the device supports ReadPropertyMultiple, if that's known yet. It's found out by
+    /// the first ReadPropertyMultiple request, e.g. by `read_all_properties()`.
+    pub fn supports_rpm(&self) -> Option<bool> {
+        target_addresses()
+            .get(&self.device_id)
+            .and_then(|target| target.rpm_supported)
+    }
+
+    fn set_rpm_supported(&self, supported: bool) {
+        if let Some(target) = target_addresses().get_mut(&self.device_id) {
+            target.rpm_supported = Some(supported);
+        }
+    }
+
+    /// Read all properties of an object, using ReadPropertyMultiple if the device supports it.
+    ///
+    /// Properties that can't be read are left out. When the object has no `property-list` and
+    /// reading ALL properties doesn't work, only the properties the stack knows of for the
+    /// object-type are read.
+    pub fn read_all_properties(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+    ) -> HashMap<ObjectPropertyId, BACnetValue> {
+        match self.try_read_all_properties(object_type, object_instance) {
+            Ok(properties) => properties,
+            Err(err) => {
+                warn!(
+                    "Failed to read {}: {}",
+                    ObjectId::new(object_type, object_instance),
+                    err
+                );
+                HashMap::new()
+            }
+        }
+    }
+
+    // Like read_all_properties(), but fails if the object doesn't exist, or the device stops
+    // answering.
+    pub(crate) fn try_read_all_properties(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+    ) -> Result<HashMap<ObjectPropertyId, BACnetValue>> {
+        if self.supports_rpm() != Some(false) {
+            match self.read_prop_multiple(
+                object_type,
+                object_instance,
+                &[bacnet_sys::BACNET_PROPERTY_ID_PROP_ALL],
+            ) {
+                Ok(results) if is_unknown_object(&results) => {
+                    return Err(BACnetErr::Error {
+                        class: ErrorClass::Object,
+                        code: ErrorCode::UnknownObject,
+                    }
+                    .into())
+                }
+                Ok(results) => return Ok(collect_values(results)),
+                Err(err) if is_fatal(&err) => return Err(err),
+                Err(err) => debug!("RPM ALL failed, reading the property-list: {}", err),
+            }
+        }
+
+        let properties = match self.read_property_list(object_type, object_instance) {
+            Ok(properties) => properties,
+            Err(err) if is_fatal(&err) => return Err(err),
+            Err(err) => {
+                debug!("no property-list, reading the known properties: {}", err);
+                return Ok(self.read_known_properties(object_type, object_instance));
+            }
+        };
+
+        let mut ret = HashMap::with_capacity(properties.len());
+        if self.supports_rpm() != Some(false) {
+            for chunk in properties.chunks(CHUNK_SIZE) {
+                self.read_chunk(object_type, object_instance, chunk, &mut ret)?;
+            }
+        } else {
+            for property in properties {
+                self.read_single(object_type, object_instance, property, &mut ret)?;
+            }
+        }
+        Ok(ret)
+    }
+
+    // The properties listed in property-list, plus the ones property-list leaves out
+    pub(crate) fn read_property_list(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+    ) -> Result<Vec<ObjectPropertyId>> {
+        let list = self.read_prop_walk(
+            object_type,
+            object_instance,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PROPERTY_LIST,
+        )?;
+        let list = match list {
+            BACnetValue::Array(values) => values,
+            v => {
+                error!("Unexpected type when reading property-list {:?}", v);
+                vec![]
+            }
+        };
+        let mut properties = vec![
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_TYPE,
+            bacnet_sys::BACNET_PROPERTY_ID_PROP_PROPERTY_LIST,
+        ];
+        for value in list {
+            match value {
+                BACnetValue::Enum(property, _) if !properties.contains(&property) => {
+                    properties.push(property)
+                }
+                BACnetValue::Enum(..) => {}
+                v => error!("Unexpected type when reading property-list {:?}", v),
+            }
+        }
+        Ok(properties)
+    }
+
+    // Read some properties with RPM. If that fails, e.g. because the answer is too large or one of
+    // the properties can't be decoded, the chunk is split in two, down to single properties which
+    // are read with ReadProperty.
+    fn read_chunk(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+        properties: &[ObjectPropertyId],
+        ret: &mut HashMap<ObjectPropertyId, BACnetValue>,
+    ) -> Result<()> {
+        if properties.len() == 1 {
+            return self.read_single(object_type, object_instance, properties[0], ret);
+        }
+        match self.read_prop_multiple(object_type, object_instance, properties) {
+            Ok(results) => ret.extend(collect_values(results)),
+            Err(err) if is_fatal(&err) => return Err(err),
+            Err(err) => {
+                debug!("Failed to read properties {:?}: {}", properties, err);
+                let (first, second) = properties.split_at(properties.len() / 2);
+                self.read_chunk(object_type, object_instance, first, ret)?;
+                self.read_chunk(object_type, object_instance, second, ret)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Read a single property with ReadProperty
+    pub(crate) fn read_single(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+        property: ObjectPropertyId,
+        ret: &mut HashMap<ObjectPropertyId, BACnetValue>,
+    ) -> Result<()> {
+        match self.read_prop_walk(object_type, object_instance, property) {
+            Ok(value) => {
+                ret.insert(property, value);
+            }
+            Err(err) if is_fatal(&err) => return Err(err),
+            Err(err) => debug!("property {}: {}", property, err),
+        }
+        Ok(())
+    }
+
+    // Read a property with ReadProperty. If it's an array too large for a single APDU, it's read
+    // an element at a time, straight away if the device is known not to segment its answers.
+    pub(crate) fn read_prop_walk(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+        property: ObjectPropertyId,
+    ) -> Result<BACnetValue> {
+        let segments = match self.capabilities() {
+            Some(capabilities) if capabilities.segmentation.is_some() => {
+                capabilities.segments_answers()
+            }
+            _ => true,
+        };
+        if !segments && crate::is_array(property) {
+            return self.read_elements(object_type, object_instance, property);
+        }
+        match self.read_prop(object_type, object_instance, property) {
+            Err(err) if err.is_segmentation_not_supported() => {
+                self.read_elements(object_type, object_instance, property)
+            }
+            ret => ret,
+        }
+    }
+
+    // Read an array an element at a time, starting with its length at index 0
+    fn read_elements(
+        &self,
+        object_type: ObjectType,
+        object_instance: u32,
+        property: ObjectPropertyId,
+    ) -> Result<BACnetValue> {
+        let len: u64 = self
+            .read_prop_at(object_type, object_instance, property, 0)?
+            .try_into()?;
+        let mut ary = Vec::with_capacity(len as usize);
+        for i in 1..=len {
+            ary.push(self.read_prop_at(object_type, object_instance, property, i as u32)?);
+        }
+        Ok(BACnetValue::Array(ary))
+    }
+}
+
+// The values that could be read
+fn collect_values(results: Vec<PropertyResult>) -> HashMap<ObjectPropertyId, BACnetValue> {
+    results
+        .into_iter()
+        .filter_map(|result| match result.value {
+            Ok(value) => Some((result.property, value)),
+            Err(err) => {
+                debug!("property {}: {}", result.property, err);
+                None
+            }
+        })
+        .collect()
+}
+
+// There's no point in reading any further properties of the object
+fn is_fatal(err: &Error) -> bool {
+    err.is_unreachable() || err.is_unknown_object()
+}
+
+// Every property failed because the object doesn't exist
+fn is_unknown_object(results: &[PropertyResult]) -> bool {
+    !results.is_empty()
+        && results
+            .iter()
+            .all(|result| matches!(&result.value, Err(err) if err.is_unknown_object()))
+}
+
+fn is_unrecognized_service(error: &BACnetErr) -> bool {
+    matches!(
+        error,
+        BACnetErr::Rejected {
+            reason: RejectReason::UnrecognizedService,
+        } | BACnetErr::Error {
+            code: ErrorCode::RejectUnrecognizedService,
+            ..
+        }
+    )
+}
+
+// The answer didn't fit in an (unsegmented) APDU
+pub(crate) extern "C" fn read_property_multiple_ack_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    service_data: *mut bacnet_sys::BACNET_CONFIRMED_SERVICE_ACK_DATA,
+) {
+    crate::catch_panic("read_property_multiple_ack_handler", || {
+        let invoke_id = unsafe { (*service_data).invoke_id };
+        let mut lock = target_addresses();
+        if let Some(target) = find_matching_device(&mut lock, src, invoke_id) {
+            let data =
+                unsafe { std::slice::from_raw_parts_mut(service_request, service_len.into()) };
+            let results = decode_ack(data);
+            if results.is_err() {
+                error!("<decode failed>");
+            }
+            target.ack = Some(results.map(Ack::PropertyResults));
+            target.request = Some((invoke_id, RequestStatus::Done));
+        }
+    });
+}
+
+// Decode a ReadPropertyMultiple-ACK, like rpm_ack_decode_service_request() does but without
+// the allocations. The results of all objects are returned together.
+fn decode_ack(data: &mut [u8]) -> Result<Vec<PropertyResult>> {
+    let mut results = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let mut object_type = 0;
+        let mut object_instance = 0;
+        let len = unsafe {
+            bacnet_sys::rpm_ack_decode_object_id(
+                data[offset..].as_mut_ptr(),
+                (data.len() - offset) as u32,
+                &mut object_type,
+                &mut object_instance,
+            )
+        };
+        if len <= 0 {
+            return Err(Error::DecodingError);
+        }
+        offset += len as usize;
+
+        loop {
+            let rest = &mut data[offset..];
+            if rest.is_empty() {
+                return Err(Error::DecodingError);
+            }
+            let len = unsafe {
+                bacnet_sys::rpm_ack_decode_object_end(rest.as_mut_ptr(), rest.len() as u32)
+            };
+            if len > 0 {
+                offset += len as usize;
+                break;
+            }
+
+            let mut property = 0;
+            let mut array_index = 0;
+            let len = unsafe {
+                bacnet_sys::rpm_ack_decode_object_property(
+                    rest.as_mut_ptr(),
+                    rest.len() as u32,
+                    &mut property,
+                    &mut array_index,
+                )
+            };
+            if len <= 0 {
+                return Err(Error::DecodingError);
+            }
+            offset += len as usize;
+
+            let (value, len) =
+                decode_result(&mut data[offset..], object_type, property, array_index)?;
+            offset += len;
+            results.push(PropertyResult {
+                property,
+                array_index: if array_index == bacnet_sys::BACNET_ARRAY_ALL {
+                    None
+                } else {
+                    Some(array_index)
+                },
+                value,
+            });
+        }
+    }
+    Ok(results)
+}
+
+// Decode the value ([4] propertyValue) or error ([5] propertyAccessError) of a property. Returns
+// it and the number of bytes used.
+fn decode_result(
+    data: &mut [u8],
+    object_type: ObjectType,
+    property: ObjectPropertyId,
+    array_index: u32,
+) -> Result<(Result<BACnetValue>, usize)> {
+    if data.is_empty() {
+        return Err(Error::DecodingError);
+    }
+    if unsafe { bacnet_sys::decode_is_opening_tag_number(data.as_mut_ptr(), 5) } {
+        let mut offset = 1;
+        let mut codes = [0u32; 2];
+        for code in codes.iter_mut() {
+            let mut tag_number = 0;
+            let mut len_value = 0;
+            if offset >= data.len() {
+                return Err(Error::DecodingError);
+            }
+            let len = unsafe {
+                bacnet_sys::bacnet_tag_number_and_value_decode(
+                    data[offset..].as_mut_ptr(),
+                    (data.len() - offset) as u32,
+                    &mut tag_number,
+                    &mut len_value,
+                )
+            };
+            if len <= 0 || len_value > 4 || offset + len as usize + len_value as usize > data.len()
+            {
+                return Err(Error::DecodingError);
+            }
+            offset += len as usize;
+            offset += unsafe {
+                bacnet_sys::decode_enumerated(data[offset..].as_mut_ptr(), len_value, code)
+            } as usize;
+        }
+        if offset >= data.len()
+            || !unsafe { bacnet_sys::decode_is_closing_tag_number(data[offset..].as_mut_ptr(), 5) }
+        {
+            return Err(Error::DecodingError);
+        }
+        offset += 1;
+        let error = crate::bacnet_error(codes[0], codes[1]);
+        return Ok((Err(error.into()), offset));
+    }
+    if !unsafe { bacnet_sys::decode_is_opening_tag_number(data.as_mut_ptr(), 4) } {
+        return Err(Error::DecodingError);
+    }
+
+    let mut offset = 1;
+    let mut values = Vec::new();
+    let mut undecodable = None;
+    loop {
+        if offset >= data.len() {
+            return Err(Error::DecodingError);
+        }
+        if unsafe { bacnet_sys::decode_is_closing_tag_number(data[offset..].as_mut_ptr(), 4) } {
+            offset += 1;
+            break;
+        }
+        if is_context_specific(data[offset]) {
+            // A constructed value, which we can't represent. Skip it.
+            offset += skip_element(&mut data[offset..]).ok_or(Error::DecodingError)?;
+            undecodable = Some(Error::UnhandledTypeTag {
+                tag_name: "context".to_string(),
+                value_tag: data[offset - 1] >> 4,
+            });
+            continue;
+        }
+        match decode_value(
+            data[offset..].as_mut_ptr(),
+            (data.len() - offset) as u32,
+            object_type,
+            property,
+        ) {
+            Ok((value, len)) if len > 0 => {
+                values.push(value);
+                offset += len;
+            }
+            // A primitive we can't decode (e.g. a date). Skip it, it only fails this property.
+            result => {
+                offset += skip_element(&mut data[offset..]).ok_or(Error::DecodingError)?;
+                undecodable = Some(result.err().unwrap_or(Error::DecodingError));
+            }
+        }
+    }
+
+    let value = match undecodable {
+        Some(err) => Err(err),
+        None => Ok(crate::collect_elements(values, property, array_index)),
+    };
+    Ok((value, offset))
+}
+
+fn is_context_specific(tag: u8) -> bool {
+    tag & 0x08 != 0
+}
+
+// The length of the element at the start of `data`, including everything up to the matching
+// closing tag if it's an opening tag
+fn skip_element(data: &mut [u8]) -> Option<usize> {
+    let mut offset = 0;
+    let mut depth = 0;
+    loop {
+        if offset >= data.len() {
+            return None;
+        }
+        let tag = data[offset];
+        let mut tag_number = 0;
+        let mut len_value = 0;
+        let len = unsafe {
+            bacnet_sys::decode_tag_number_and_value(
+                data[offset..].as_mut_ptr(),
+                &mut tag_number,
+                &mut len_value,
+            )
+        } as usize;
+        offset += len;
+        match tag & 0x07 {
+            // Opening tag
+            6 => depth += 1,
+            // Closing tag
+            7 => depth -= 1,
+            // An application tagged boolean has its value in the tag itself
+            _ if !is_context_specific(tag) && tag_number == 1 => {}
+            _ => offset += len_value as usize,
+        }
+        if depth <= 0 {
+            return if offset <= data.len() {
+                Some(offset)
+            } else {
+                None
+            };
+        }
+    }
+}
@@ -0,0 +1,796 @@
+//! Hosting a local BACnet device, with objects defined in Rust
+//!
+//! The stack answers ReadProperty, ReadPropertyMultiple, WriteProperty and WritePropertyMultiple
+//! requests from its table of object types (see `Device_Init()`). The table installed here has the
+//! stack's own Device object, and for each supported object type functions that look up the
+//! objects registered with `add_object()`. Each property of such an object is either a value kept
+//! here (see `LocalObject::value()` and `set_value()`), or a getter and an optional setter.
+//!
+//! Like everything else in the stack, requests are only answered while the stack is driven
+//! forward, e.g. by `bacnet::poll()` or an on-going request.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::result;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::value::BACnetValue;
+use crate::{decode_values, Error, ErrorClass, ErrorCode, Result};
+
+/// The error class and code to answer a request with
+pub type PropertyError = (ErrorClass, ErrorCode);
+
+type Getter = Arc<dyn Fn() -> BACnetValue + Send + Sync>;
+type Setter = Arc<dyn Fn(BACnetValue, u8) -> result::Result<(), PropertyError> + Send + Sync>;
+
+// The object types that objects can be hosted for. Each gets an entry in the stack's object table,
+// after the Device object.
+macro_rules! object_table {
+    ($($object_type:ident,)*) => {
+        const OBJECT_TYPES: &[bacnet_sys::BACNET_OBJECT_TYPE] = &[$(bacnet_sys::$object_type,)*];
+
+        static OBJECT_TABLE: &[bacnet_sys::object_functions_t] = &[
+            device_functions(),
+            $(object_functions::<{ bacnet_sys::$object_type }>(),)*
+            end_of_table(),
+        ];
+    };
+}
+
+object_table! {
+    BACNET_OBJECT_TYPE_OBJECT_ANALOG_INPUT,
+    BACNET_OBJECT_TYPE_OBJECT_ANALOG_OUTPUT,
+    BACNET_OBJECT_TYPE_OBJECT_ANALOG_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_BINARY_INPUT,
+    BACNET_OBJECT_TYPE_OBJECT_BINARY_OUTPUT,
+    BACNET_OBJECT_TYPE_OBJECT_BINARY_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_INPUT,
+    BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_OUTPUT,
+    BACNET_OBJECT_TYPE_OBJECT_MULTI_STATE_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_ACCUMULATOR,
+    BACNET_OBJECT_TYPE_OBJECT_CHARACTERSTRING_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_DATETIME_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_INTEGER_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_LARGE_ANALOG_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_OCTETSTRING_VALUE,
+    BACNET_OBJECT_TYPE_OBJECT_POSITIVE_INTEGER_VALUE,
+}
+
+// Every object has these, see `property_lists()`
+static REQUIRED_PROPERTIES: [i32; 4] = [
+    bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER as i32,
+    bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME as i32,
+    bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_TYPE as i32,
+    -1,
+];
+static NO_PROPERTIES: [i32; 1] = [-1];
+
+// The first proprietary property identifier
+const FIRST_PROPRIETARY_PROPERTY: u32 = 512;
+
+lazy_static! {
+    /// The hosted objects. Set by `start()`, `add_object()` and friends.
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+#[derive(Default)]
+struct Registry {
+    // The instance of the hosted device, once started
+    device_id: Option<u32>,
+    objects: BTreeMap<(bacnet_sys::BACNET_OBJECT_TYPE, u32), LocalObject>,
+    property_lists: HashMap<bacnet_sys::BACNET_OBJECT_TYPE, PropertyLists>,
+}
+
+// The properties ReadPropertyMultiple answers for ALL and OPTIONAL, and the property-list, of the
+// objects of a type. The stack only asks for them per object type, so they are all properties the
+// hosted objects of the type have.
+//
+// The stack holds on to the lists after asking for them, so they are never freed: when the
+// properties change, the lists are replaced by new ones and the old ones leaked. That only happens
+// when an object adds a property no other object of the type has, or removes the last one that did.
+struct PropertyLists {
+    properties: BTreeSet<u32>,
+    optional: &'static [i32],
+    proprietary: &'static [i32],
+}
+
+impl Registry {
+    // Update the property lists of a type after one of its objects was added or removed
+    fn update_property_lists(&mut self, object_type: bacnet_sys::BACNET_OBJECT_TYPE) {
+        let properties: BTreeSet<u32> = self
+            .objects
+            .range((object_type, 0)..=(object_type, u32::MAX))
+            .flat_map(|(_, object)| object.properties.keys())
+            .filter(|property| !REQUIRED_PROPERTIES.contains(&(**property as i32)))
+            .copied()
+            .collect();
+        let lists = self
+            .property_lists
+            .entry(object_type)
+            .or_insert_with(|| PropertyLists {
+                properties: BTreeSet::new(),
+                optional: &NO_PROPERTIES,
+                proprietary: &NO_PROPERTIES,
+            });
+        if lists.properties != properties {
+            let (proprietary, optional): (Vec<u32>, Vec<u32>) = properties
+                .iter()
+                .partition(|property| **property >= FIRST_PROPRIETARY_PROPERTY);
+            lists.optional = leak_list(optional);
+            lists.proprietary = leak_list(proprietary);
+            lists.properties = properties;
+        }
+    }
+}
+
+/// An object hosted by the local device, with the properties it answers requests for.
+///
+/// The object-identifier, object-name and object-type properties are answered from the object
+/// itself, unless they're defined with one of the builder methods.
+// LocalObject::new(OBJECT_ANALOG_INPUT, 1, "Outside air").getter(PROP_PRESENT_VALUE, || ...)
+pub struct LocalObject {
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+    name: String,
+    properties: BTreeMap<u32, Property>,
+}
+
+enum Property {
+    /// A value kept by the server, changed by `set_value()`, and by the network if `writable`
+    Value {
+        value: BACnetValue,
+        writable: bool,
+    },
+    Function {
+        get: Getter,
+        set: Option<Setter>,
+    },
+}
+
+impl LocalObject {
+    pub fn new(
+        object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+        object_instance: u32,
+        name: &str,
+    ) -> Self {
+        LocalObject {
+            object_type,
+            object_instance,
+            name: name.to_string(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// A read-only property with the given value, which can be changed with `set_value()`
+    pub fn value(mut self, property: bacnet_sys::BACNET_PROPERTY_ID, value: BACnetValue) -> Self {
+        self.properties.insert(
+            property,
+            Property::Value {
+                value,
+                writable: false,
+            },
+        );
+        self
+    }
+
+    /// Like `value()`, but it can also be written over the network, with a value of the same type.
+    /// The priority of the write is ignored.
+    pub fn writable_value(
+        mut self,
+        property: bacnet_sys::BACNET_PROPERTY_ID,
+        value: BACnetValue,
+    ) -> Self {
+        self.properties.insert(
+            property,
+            Property::Value {
+                value,
+                writable: true,
+            },
+        );
+        self
+    }
+
+    /// A read-only property, read by calling `get`
+    pub fn getter<G>(mut self, property: bacnet_sys::BACNET_PROPERTY_ID, get: G) -> Self
+    where
+        G: Fn() -> BACnetValue + Send + Sync + 'static,
+    {
+        self.properties.insert(
+            property,
+            Property::Function {
+                get: Arc::new(get),
+                set: None,
+            },
+        );
+        self
+    }
+
+    /// A writable property, read by calling `get` and written by calling `set` with the new value
+    /// and the priority of the write (1 to 16, 16 if none was given). A write can be refused by
+    /// returning the error to answer with, e.g. `(ErrorClass::Property,
+    /// ErrorCode::ValueOutOfRange)`.
+    ///
+    /// Writes of a single array element read the whole array with `get`, and `set` it with the
+    /// element replaced.
+    pub fn property<G, S>(
+        mut self,
+        property: bacnet_sys::BACNET_PROPERTY_ID,
+        get: G,
+        set: S,
+    ) -> Self
+    where
+        G: Fn() -> BACnetValue + Send + Sync + 'static,
+        S: Fn(BACnetValue, u8) -> result::Result<(), PropertyError> + Send + Sync + 'static,
+    {
+        self.properties.insert(
+            property,
+            Property::Function {
+                get: Arc::new(get),
+                set: Some(Arc::new(set)),
+            },
+        );
+        self
+    }
+
+    pub fn object_type(&self) -> bacnet_sys::BACNET_OBJECT_TYPE {
+        self.object_type
+    }
+
+    pub fn object_instance(&self) -> u32 {
+        self.object_instance
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for LocalObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LocalObject")
+            .field("object_type", &self.object_type)
+            .field("object_instance", &self.object_instance)
+            .field("name", &self.name)
+            .field("properties", &self.properties.keys())
+            .finish()
+    }
+}
+
+/// Start hosting a device with the given instance and name, and announce it with an I-Am.
+///
+/// Objects can be added before or after starting. Starting again changes the instance and name.
+// server::start(1234, "Gateway")?; server::add_object(LocalObject::new(...))?; bacnet::poll(...)
+pub fn start(device_id: u32, name: &str) -> Result<()> {
+    if device_id >= bacnet_sys::BACNET_MAX_INSTANCE {
+        return Err(Error::InvalidDeviceId { device_id });
+    }
+    let invalid_name = || Error::InvalidObjectName {
+        name: name.to_string(),
+    };
+    let c_name = CString::new(name).map_err(|_| invalid_name())?;
+    if name.is_empty() {
+        return Err(invalid_name());
+    }
+    crate::init_stack();
+
+    let mut registry = registry();
+    if registry.objects.values().any(|object| object.name == name) {
+        return Err(Error::DuplicateObjectName {
+            name: name.to_string(),
+        });
+    }
+    unsafe {
+        if !bacnet_sys::Device_Object_Name_ANSI_Init(c_name.as_ptr()) {
+            return Err(invalid_name());
+        }
+        bacnet_sys::Device_Set_Object_Instance_Number(device_id);
+    }
+    registry.device_id = Some(device_id);
+    drop(registry);
+    debug!("hosting device {} ({})", device_id, name);
+
+    unsafe {
+        bacnet_sys::apdu_set_confirmed_handler(
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_READ_PROP_MULTIPLE,
+            Some(bacnet_sys::handler_read_property_multiple),
+        );
+        bacnet_sys::apdu_set_confirmed_handler(
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_WRITE_PROPERTY,
+            Some(bacnet_sys::handler_write_property),
+        );
+        bacnet_sys::apdu_set_confirmed_handler(
+            bacnet_sys::BACNET_CONFIRMED_SERVICE_SERVICE_CONFIRMED_WRITE_PROP_MULTIPLE,
+            Some(bacnet_sys::handler_write_property_multiple),
+        );
+        bacnet_sys::Send_I_Am(
+            std::ptr::addr_of_mut!(bacnet_sys::Handler_Transmit_Buffer) as *mut u8
+        );
+    }
+    Ok(())
+}
+
+/// The instance of the hosted device, if `start()` was called
+pub fn device_id() -> Option<u32> {
+    registry().device_id
+}
+
+/// Host an object. Its type has to be one of the supported ones (the input, output and value
+/// objects), and its name has to be unique in the device.
+///
+/// The stack asks for the properties of objects per type, not per object, so ReadPropertyMultiple
+/// of ALL or OPTIONAL, and the property-list property, give the properties of all hosted objects
+/// of the type. Those an object doesn't have are answered with an unknown-property error.
+pub fn add_object(object: LocalObject) -> Result<()> {
+    let object_type = object.object_type;
+    let object_instance = object.object_instance;
+    if !OBJECT_TYPES.contains(&object_type) {
+        return Err(Error::UnsupportedObjectType { object_type });
+    }
+    if object.name.is_empty()
+        || object.name.contains('\0')
+        || object.name.len() >= bacnet_sys::MAX_CHARACTER_STRING_BYTES as usize
+    {
+        return Err(Error::InvalidObjectName { name: object.name });
+    }
+
+    let mut registry = registry();
+    if registry
+        .objects
+        .contains_key(&(object_type, object_instance))
+    {
+        return Err(Error::ObjectExists {
+            object_type,
+            object_instance,
+        });
+    }
+    let is_device_name = registry.device_id.is_some() && is_device_name(&object.name);
+    if is_device_name
+        || registry
+            .objects
+            .values()
+            .any(|other| other.name == object.name)
+    {
+        return Err(Error::DuplicateObjectName { name: object.name });
+    }
+
+    debug!("hosting {:?}", object);
+    registry
+        .objects
+        .insert((object_type, object_instance), object);
+    registry.update_property_lists(object_type);
+    Ok(())
+}
+
+/// Stop hosting an object, returning it
+pub fn remove_object(
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+) -> Option<LocalObject> {
+    let mut registry = registry();
+    let object = registry.objects.remove(&(object_type, object_instance))?;
+    registry.update_property_lists(object_type);
+    Some(object)
+}
+
+/// The current value of a property defined with `value()` or `writable_value()`
+pub fn value(
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+    property: bacnet_sys::BACNET_PROPERTY_ID,
+) -> Option<BACnetValue> {
+    let registry = registry();
+    match registry
+        .objects
+        .get(&(object_type, object_instance))?
+        .properties
+        .get(&property)?
+    {
+        Property::Value { value, .. } => Some(value.clone()),
+        Property::Function { .. } => None,
+    }
+}
+
+/// Change the value of a property defined with `value()` or `writable_value()`
+pub fn set_value(
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+    property: bacnet_sys::BACNET_PROPERTY_ID,
+    value: BACnetValue,
+) -> Result<()> {
+    let mut registry = registry();
+    match registry
+        .objects
+        .get_mut(&(object_type, object_instance))
+        .and_then(|object| object.properties.get_mut(&property))
+    {
+        Some(Property::Value { value: current, .. }) => {
+            *current = value;
+            Ok(())
+        }
+        _ => Err(Error::NoLocalValue {
+            object_type,
+            object_instance,
+            property,
+        }),
+    }
+}
+
+// The object table to give `Device_Init()`
+pub(crate) fn object_table() -> *mut bacnet_sys::object_functions_t {
+    // The stack only reads the table
+    OBJECT_TABLE.as_ptr() as *mut _
+}
+
+// Lock the registry. Getters and setters are called without holding the lock, so they can use the
+// functions of this module.
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Whether the hosted device has the given name, which can't contain NUL characters
+fn is_device_name(name: &str) -> bool {
+    let name = CString::new(name).unwrap_or_default();
+    let mut device_name = bacnet_sys::BACNET_CHARACTER_STRING::default();
+    unsafe {
+        bacnet_sys::Device_Object_Name(
+            bacnet_sys::Device_Object_Instance_Number(),
+            &mut device_name,
+        ) && bacnet_sys::characterstring_ansi_same(&mut device_name, name.as_ptr())
+    }
+}
+
+fn leak_list(properties: Vec<u32>) -> &'static [i32] {
+    let list: Vec<i32> = properties
+        .into_iter()
+        .map(|property| property as i32)
+        .chain(Some(-1))
+        .collect();
+    Box::leak(list.into_boxed_slice())
+}
+
+// The stack's own Device object, like in its default table. It can't have an init function, as
+// that would call `Device_Init()` again.
+const fn device_functions() -> bacnet_sys::object_functions_t {
+    bacnet_sys::object_functions_t {
+        Object_Type: bacnet_sys::BACNET_OBJECT_TYPE_OBJECT_DEVICE,
+        Object_Init: None,
+        Object_Count: Some(bacnet_sys::Device_Count),
+        Object_Index_To_Instance: Some(bacnet_sys::Device_Index_To_Instance),
+        Object_Valid_Instance: Some(bacnet_sys::Device_Valid_Object_Instance_Number),
+        Object_Name: Some(bacnet_sys::Device_Object_Name),
+        Object_Read_Property: Some(bacnet_sys::Device_Read_Property_Local),
+        Object_Write_Property: Some(bacnet_sys::Device_Write_Property_Local),
+        Object_RPM_List: Some(bacnet_sys::Device_Property_Lists),
+        Object_RR_Info: Some(bacnet_sys::DeviceGetRRInfo),
+        Object_Iterator: None,
+        Object_Value_List: None,
+        Object_COV: None,
+        Object_COV_Clear: None,
+        Object_Intrinsic_Reporting: None,
+    }
+}
+
+// The functions of an object type. The stack doesn't pass the object type to most of them, so
+// there is a copy of them for each type.
+const fn object_functions<const T: bacnet_sys::BACNET_OBJECT_TYPE>(
+) -> bacnet_sys::object_functions_t {
+    bacnet_sys::object_functions_t {
+        Object_Type: T,
+        Object_Init: None,
+        Object_Count: Some(object_count::<T>),
+        Object_Index_To_Instance: Some(object_index_to_instance::<T>),
+        Object_Valid_Instance: Some(object_valid_instance::<T>),
+        Object_Name: Some(object_name::<T>),
+        Object_Read_Property: Some(read_property),
+        Object_Write_Property: Some(write_property),
+        Object_RPM_List: Some(property_lists::<T>),
+        Object_RR_Info: None,
+        Object_Iterator: None,
+        Object_Value_List: None,
+        Object_COV: None,
+        Object_COV_Clear: None,
+        Object_Intrinsic_Reporting: None,
+    }
+}
+
+// The stack stops at the first entry with an object type of MAX_BACNET_OBJECT_TYPE
+const fn end_of_table() -> bacnet_sys::object_functions_t {
+    bacnet_sys::object_functions_t {
+        Object_Type: bacnet_sys::BACNET_OBJECT_TYPE_MAX_BACNET_OBJECT_TYPE,
+        Object_Init: None,
+        Object_Count: None,
+        Object_Index_To_Instance: None,
+        Object_Valid_Instance: None,
+        Object_Name: None,
+        Object_Read_Property: None,
+        Object_Write_Property: None,
+        Object_RPM_List: None,
+        Object_RR_Info: None,
+        Object_Iterator: None,
+        Object_Value_List: None,
+        Object_COV: None,
+        Object_COV_Clear: None,
+        Object_Intrinsic_Reporting: None,
+    }
+}
+
+extern "C" fn object_count<const T: bacnet_sys::BACNET_OBJECT_TYPE>() -> u32 {
+    let mut count = 0;
+    crate::catch_panic("object_count", || {
+        count = registry().objects.range((T, 0)..=(T, u32::MAX)).count() as u32;
+    });
+    count
+}
+
+// Objects are indexed in the order of their instance. An invalid index gives an invalid instance.
+extern "C" fn object_index_to_instance<const T: bacnet_sys::BACNET_OBJECT_TYPE>(index: u32) -> u32 {
+    let mut instance = bacnet_sys::BACNET_MAX_INSTANCE;
+    crate::catch_panic("object_index_to_instance", || {
+        if let Some(((_, object_instance), _)) = registry()
+            .objects
+            .range((T, 0)..=(T, u32::MAX))
+            .nth(index as usize)
+        {
+            instance = *object_instance;
+        }
+    });
+    instance
+}
+
+extern "C" fn object_valid_instance<const T: bacnet_sys::BACNET_OBJECT_TYPE>(
+    object_instance: u32,
+) -> bool {
+    let mut valid = false;
+    crate::catch_panic("object_valid_instance", || {
+        valid = registry().objects.contains_key(&(T, object_instance));
+    });
+    valid
+}
+
+extern "C" fn object_name<const T: bacnet_sys::BACNET_OBJECT_TYPE>(
+    object_instance: u32,
+    object_name: *mut bacnet_sys::BACNET_CHARACTER_STRING,
+) -> bool {
+    let mut found = false;
+    crate::catch_panic("object_name", || {
+        if let Some(object) = registry().objects.get(&(T, object_instance)) {
+            found = unsafe {
+                bacnet_sys::characterstring_init(
+                    object_name,
+                    bacnet_sys::BACNET_CHARACTER_STRING_ENCODING_CHARACTER_UTF8 as u8,
+                    object.name.as_ptr() as *const _,
+                    object.name.len(),
+                )
+            };
+        }
+    });
+    found
+}
+
+extern "C" fn property_lists<const T: bacnet_sys::BACNET_OBJECT_TYPE>(
+    required: *mut *const i32,
+    optional: *mut *const i32,
+    proprietary: *mut *const i32,
+) {
+    crate::catch_panic("property_lists", || {
+        let registry = registry();
+        let (optional_list, proprietary_list) = match registry.property_lists.get(&T) {
+            Some(lists) => (lists.optional, lists.proprietary),
+            None => (&NO_PROPERTIES[..], &NO_PROPERTIES[..]),
+        };
+        unsafe {
+            if !required.is_null() {
+                *required = REQUIRED_PROPERTIES.as_ptr();
+            }
+            if !optional.is_null() {
+                *optional = optional_list.as_ptr();
+            }
+            if !proprietary.is_null() {
+                *proprietary = proprietary_list.as_ptr();
+            }
+        }
+    });
+}
+
+// Encode the value of a property into `rpdata.application_data`, returning the encoded length, or
+// BACNET_STATUS_ERROR (or BACNET_STATUS_ABORT if it doesn't fit) with the error set in `rpdata`.
+extern "C" fn read_property(rpdata: *mut bacnet_sys::BACNET_READ_PROPERTY_DATA) -> i32 {
+    let rpdata = unsafe { &mut *rpdata };
+    let mut len = bacnet_sys::BACNET_STATUS_ERROR;
+    crate::catch_panic("read_property", || {
+        let value = property_value(
+            rpdata.object_type,
+            rpdata.object_instance,
+            rpdata.object_property,
+        )
+        .and_then(|value| match rpdata.array_index {
+            bacnet_sys::BACNET_ARRAY_ALL => Ok(value),
+            index => element(value, index),
+        });
+        let value = match value {
+            Ok(value) => value,
+            Err((class, code)) => {
+                rpdata.error_class = class.to_u32();
+                rpdata.error_code = code.to_u32();
+                return;
+            }
+        };
+
+        let mut buf = vec![];
+        if value.encode(&mut buf).is_err() {
+            rpdata.error_class = ErrorClass::Property.to_u32();
+            rpdata.error_code = ErrorCode::ValueOutOfRange.to_u32();
+        } else if buf.len() > rpdata.application_data_len as usize {
+            rpdata.error_class = ErrorClass::Services.to_u32();
+            rpdata.error_code =
+                bacnet_sys::BACNET_ERROR_CODE_ERROR_CODE_ABORT_SEGMENTATION_NOT_SUPPORTED;
+            len = bacnet_sys::BACNET_STATUS_ABORT;
+        } else {
+            unsafe {
+                std::ptr::copy_nonoverlapping(buf.as_ptr(), rpdata.application_data, buf.len());
+            }
+            len = buf.len() as i32;
+        }
+    });
+    len
+}
+
+// Write the value in `wp_data`, returning whether that worked, with the error set in `wp_data` if
+// it didn't.
+extern "C" fn write_property(wp_data: *mut bacnet_sys::BACNET_WRITE_PROPERTY_DATA) -> bool {
+    let wp_data = unsafe { &mut *wp_data };
+    let mut written = false;
+    crate::catch_panic("write_property", || {
+        let len =
+            (wp_data.application_data_len.max(0) as usize).min(wp_data.application_data.len());
+        let result = match decode_values(&mut wp_data.application_data[..len]) {
+            Ok(value) => write_value(
+                wp_data.object_type,
+                wp_data.object_instance,
+                wp_data.object_property,
+                wp_data.array_index,
+                value,
+                wp_data.priority,
+            ),
+            Err(_) => Err((ErrorClass::Property, ErrorCode::InvalidDataType)),
+        };
+        match result {
+            Ok(()) => written = true,
+            Err((class, code)) => {
+                wp_data.error_class = class.to_u32();
+                wp_data.error_code = code.to_u32();
+            }
+        }
+    });
+    written
+}
+
+// The whole value of a property of a hosted object
+fn property_value(
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+    property: bacnet_sys::BACNET_PROPERTY_ID,
+) -> result::Result<BACnetValue, PropertyError> {
+    let get = {
+        let registry = registry();
+        let object = registry
+            .objects
+            .get(&(object_type, object_instance))
+            .ok_or((ErrorClass::Object, ErrorCode::UnknownObject))?;
+        match object.properties.get(&property) {
+            Some(Property::Value { value, .. }) => return Ok(value.clone()),
+            Some(Property::Function { get, .. }) => get.clone(),
+            None => return builtin_value(object, property),
+        }
+    };
+    Ok(get())
+}
+
+// The properties that come from the object itself
+fn builtin_value(
+    object: &LocalObject,
+    property: bacnet_sys::BACNET_PROPERTY_ID,
+) -> result::Result<BACnetValue, PropertyError> {
+    match property {
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_IDENTIFIER => Ok(BACnetValue::ObjectId {
+            object_type: object.object_type,
+            object_instance: object.object_instance,
+        }),
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_NAME => {
+            Ok(BACnetValue::String(object.name.clone()))
+        }
+        bacnet_sys::BACNET_PROPERTY_ID_PROP_OBJECT_TYPE => {
+            Ok(BACnetValue::Enum(object.object_type, None))
+        }
+        _ => Err((ErrorClass::Property, ErrorCode::UnknownProperty)),
+    }
+}
+
+fn write_value(
+    object_type: bacnet_sys::BACNET_OBJECT_TYPE,
+    object_instance: u32,
+    property: bacnet_sys::BACNET_PROPERTY_ID,
+    array_index: u32,
+    value: BACnetValue,
+    priority: u8,
+) -> result::Result<(), PropertyError> {
+    let set = {
+        let mut registry = registry();
+        let object = registry
+            .objects
+            .get_mut(&(object_type, object_instance))
+            .ok_or((ErrorClass::Object, ErrorCode::UnknownObject))?;
+        match object.properties.get_mut(&property) {
+            Some(Property::Value {
+                value: current,
+                writable: true,
+            }) => {
+                let value = match (array_index, current.clone(), value) {
+                    (bacnet_sys::BACNET_ARRAY_ALL, BACnetValue::Array(_), value) => as_array(value),
+                    (bacnet_sys::BACNET_ARRAY_ALL, _, value) => value,
+                    (index, array, value) => replace_element(array, index, value)?,
+                };
+                if mem::discriminant(current) != mem::discriminant(&value) {
+                    return Err((ErrorClass::Property, ErrorCode::InvalidDataType));
+                }
+                *current = value;
+                return Ok(());
+            }
+            Some(Property::Function {
+                get,
+                set: Some(set),
+            }) => (get.clone(), set.clone()),
+            Some(_) => return Err((ErrorClass::Property, ErrorCode::WriteAccessDenied)),
+            None => {
+                builtin_value(object, property)?;
+                return Err((ErrorClass::Property, ErrorCode::WriteAccessDenied));
+            }
+        }
+    };
+
+    let (get, set) = set;
+    let value = match array_index {
+        bacnet_sys::BACNET_ARRAY_ALL => value,
+        index => replace_element(get(), index, value)?,
+    };
+    set(value, priority)
+}
+
+// A single element, written to an array property, is the whole array
+fn as_array(value: BACnetValue) -> BACnetValue {
+    match value {
+        BACnetValue::Array(_) => value,
+        BACnetValue::Null => BACnetValue::Array(vec![]),
+        value => BACnetValue::Array(vec![value]),
+    }
+}
+
+// Element `index` of an array, where element 0 is the length
+fn element(value: BACnetValue, index: u32) -> result::Result<BACnetValue, PropertyError> {
+    match value {
+        BACnetValue::Array(values) if index == 0 => Ok(BACnetValue::Uint(values.len() as u64)),
+        BACnetValue::Array(mut values) if index as usize <= values.len() => {
+            Ok(values.swap_remove(index as usize - 1))
+        }
+        BACnetValue::Array(_) => Err((ErrorClass::Property, ErrorCode::InvalidArrayIndex)),
+        _ => Err((ErrorClass::Property, ErrorCode::PropertyIsNotAnArray)),
+    }
+}
+
+// The array with element `index` replaced by `value`. The length can't be written.
+fn replace_element(
+    array: BACnetValue,
+    index: u32,
+    value: BACnetValue,
+) -> result::Result<BACnetValue, PropertyError> {
+    match array {
+        BACnetValue::Array(mut values) if index >= 1 && index as usize <= values.len() => {
+            values[index as usize - 1] = value;
+            Ok(BACnetValue::Array(values))
+        }
+        BACnetValue::Array(_) => Err((ErrorClass::Property, ErrorCode::InvalidArrayIndex)),
+        _ => Err((ErrorClass::Property, ErrorCode::PropertyIsNotAnArray)),
+    }
+}
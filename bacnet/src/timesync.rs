@@ -0,0 +1,241 @@
+//! Time-Synchronization and UTC-Time-Synchronization
+//!
+//! Sending is done with the `TimeSync` builder, either as a broadcast, to a given network, or (via
+//! `BACnetDevice::time_sync()`) to a single device we are connected to.
+//!
+//! Incoming time synchronization requests are reported on the channel returned by `subscribe()`.
+//! Like everything else in the stack they are only processed while the stack is driven forward,
+//! e.g. by `bacnet::poll()` or an on-going request.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike,
+};
+
+lazy_static! {
+    /// Where to report incoming time synchronization requests. Set by `subscribe()`.
+    static ref TIME_SYNC_SUBSCRIBER: Mutex<Option<Sender<TimeSyncRequest>>> = Mutex::new(None);
+}
+
+/// A Time-Synchronization or UTC-Time-Synchronization request received from the network.
+#[derive(Debug, Clone)]
+pub struct TimeSyncRequest {
+    /// `true` if this was a UTC-Time-Synchronization request, in which case `time` is in UTC.
+    /// Otherwise `time` is the local time of the sender.
+    pub utc: bool,
+    pub time: NaiveDateTime,
+    pub mac_addr: [u8; 6],
+    pub network_number: u16,
+    pub addr: [u8; 6],
+}
+
+/// Start receiving incoming time synchronization requests on the returned channel.
+///
+/// Only the most recent subscriber receives requests.
+pub fn subscribe() -> Receiver<TimeSyncRequest> {
+    crate::init_stack();
+    let (tx, rx) = channel();
+    if let Ok(mut lock) = TIME_SYNC_SUBSCRIBER.lock() {
+        *lock = Some(tx);
+    }
+    rx
+}
+
+/// A time synchronization to send out.
+#[derive(Debug, Clone)]
+pub struct TimeSync {
+    time: DateTime<FixedOffset>,
+
+    /// Send UTC-Time-Synchronization instead of (local) Time-Synchronization
+    utc: bool,
+
+    /// Restrict the broadcast to the given network, default is `None` which means a local
+    /// broadcast.
+    subnet: Option<u16>,
+}
+
+// TimeSync::now().utc(true).subnet(5).send()
+impl TimeSync {
+    /// Synchronize to the given time. For (local) Time-Synchronization the device receives the
+    /// wall-clock time in the time zone of `time`.
+    pub fn new<Tz: TimeZone>(time: DateTime<Tz>) -> Self {
+        TimeSync {
+            time: time.fixed_offset(),
+            utc: false,
+            subnet: None,
+        }
+    }
+
+    /// Synchronize to the current local time of this machine
+    pub fn now() -> Self {
+        Self::new(Local::now())
+    }
+
+    /// Send UTC-Time-Synchronization instead of Time-Synchronization. Default: false
+    pub fn utc(mut self, utc: bool) -> Self {
+        self.utc = utc;
+        self
+    }
+
+    pub fn subnet<S>(mut self, subnet: S) -> Self
+    where
+        S: Into<Option<u16>>,
+    {
+        self.subnet = subnet.into();
+        self
+    }
+
+    /// Broadcast the time synchronization, either locally or to the configured network.
+    pub fn send(self) {
+        crate::init_stack();
+
+        let mut dest = bacnet_sys::BACNET_ADDRESS::default();
+        if let Some(subnet) = self.subnet {
+            dest.net = subnet;
+        } else {
+            unsafe {
+                bacnet_sys::bip_get_broadcast_address(&mut dest as *mut _);
+            }
+        }
+        self.send_to(&mut dest);
+    }
+
+    /// Send the time synchronization to the given address
+    pub(crate) fn send_to(&self, dest: &mut bacnet_sys::BACNET_ADDRESS) {
+        let (mut bdate, mut btime) = if self.utc {
+            to_bacnet_date_time(self.time.naive_utc())
+        } else {
+            to_bacnet_date_time(self.time.naive_local())
+        };
+        debug!(
+            "sending time synchronization (utc = {}) {}",
+            self.utc, self.time
+        );
+        unsafe {
+            if self.utc {
+                bacnet_sys::Send_TimeSyncUTC_Remote(dest, &mut bdate, &mut btime);
+            } else {
+                bacnet_sys::Send_TimeSync_Remote(dest, &mut bdate, &mut btime);
+            }
+        }
+    }
+}
+
+impl From<SystemTime> for TimeSync {
+    fn from(time: SystemTime) -> Self {
+        Self::new(DateTime::<Local>::from(time))
+    }
+}
+
+pub(crate) fn to_bacnet_date_time(
+    time: NaiveDateTime,
+) -> (bacnet_sys::BACNET_DATE, bacnet_sys::BACNET_TIME) {
+    let bdate = bacnet_sys::BACNET_DATE {
+        year: time.year() as u16,
+        month: time.month() as u8,
+        day: time.day() as u8,
+        wday: time.weekday().number_from_monday() as u8,
+    };
+    // nanosecond() goes above 1e9 during a leap second
+    let hundredths = (time.nanosecond() / 10_000_000).min(99);
+    let btime = bacnet_sys::BACNET_TIME {
+        hour: time.hour() as u8,
+        min: time.minute() as u8,
+        sec: time.second() as u8,
+        hundredths: hundredths as u8,
+    };
+    (bdate, btime)
+}
+
+pub(crate) fn from_bacnet_date_time(
+    bdate: &bacnet_sys::BACNET_DATE,
+    btime: &bacnet_sys::BACNET_TIME,
+) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(bdate.year.into(), bdate.month.into(), bdate.day.into())?
+        .and_hms_milli_opt(
+            btime.hour.into(),
+            btime.min.into(),
+            btime.sec.into(),
+            u32::from(btime.hundredths) * 10,
+        )
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn time_sync_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+) {
+    crate::catch_panic("time_sync_handler", || {
+        handle_time_sync(service_request, service_len, src, false);
+    });
+}
+
+#[no_mangle]
+pub(crate) extern "C" fn utc_time_sync_handler(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+) {
+    crate::catch_panic("utc_time_sync_handler", || {
+        handle_time_sync(service_request, service_len, src, true);
+    });
+}
+
+fn handle_time_sync(
+    service_request: *mut u8,
+    service_len: u16,
+    src: *mut bacnet_sys::BACNET_ADDRESS,
+    utc: bool,
+) {
+    let mut bdate = bacnet_sys::BACNET_DATE::default();
+    let mut btime = bacnet_sys::BACNET_TIME::default();
+
+    // Both services share the same encoding of the service request
+    let len = unsafe {
+        bacnet_sys::timesync_decode_service_request(
+            service_request,
+            service_len.into(),
+            &mut bdate,
+            &mut btime,
+        )
+    };
+    if len <= 0 {
+        error!("unable to decode time synchronization request...");
+        return;
+    }
+    let time = match from_bacnet_date_time(&bdate, &btime) {
+        Some(time) => time,
+        None => {
+            warn!(
+                "ignoring time synchronization with an invalid date/time {:?} {:?}",
+                bdate, btime
+            );
+            return;
+        }
+    };
+    debug!("time synchronization (utc = {}) {}", utc, time);
+
+    let (mac_addr, network_number, addr) = crate::source_address(src);
+
+    if let Ok(mut lock) = TIME_SYNC_SUBSCRIBER.lock() {
+        if let Some(tx) = lock.as_ref() {
+            if tx
+                .send(TimeSyncRequest {
+                    utc,
+                    time,
+                    mac_addr,
+                    network_number,
+                    addr,
+                })
+                .is_err()
+            {
+                // The receiver has gone away
+                *lock = None;
+            }
+        }
+    }
+}
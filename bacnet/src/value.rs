@@ -3,7 +3,15 @@ use crate::Error;
 ///
 use std::convert::TryInto;
 
-#[derive(Debug)]
+/// With the `serde` feature, values are (de)serialized with their type next to the value, e.g.
+/// `{"type": "real", "value": 21.5}`, `{"type": "enum", "value": {"value": 1, "name": "active"}}`
+/// or `{"type": "object-id", "value": {"object_type": 0, "object_instance": 1}}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "kebab-case")
+)]
 pub enum BACnetValue {
     Null, // Yes!
     Bool(bool),
@@ -14,7 +22,15 @@ pub enum BACnetValue {
     String(String), // BACNET_CHARACTER_STRING
     Bytes(Vec<u8>), // BACNET_OCTET_STRING
     BitString(Vec<bool>),
-    Enum(u32, Option<String>), // Enumerated values also have string representations...
+    // Enumerated values also have string representations...
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            serialize_with = "serialize_enum",
+            deserialize_with = "deserialize_enum"
+        )
+    )]
+    Enum(u32, Option<String>),
     // A reference to an object, used during interrogation of the device (object-list)
     ObjectId {
         object_type: u32,
@@ -44,3 +60,194 @@ impl TryInto<u64> for BACnetValue {
         })
     }
 }
+
+impl BACnetValue {
+    /// Append the application tagged encoding of the value to `buf`. Arrays are encoded as their
+    /// elements one after another.
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let mut value = bacnet_sys::BACNET_APPLICATION_DATA_VALUE::default();
+        let tag = match self {
+            BACnetValue::Null => bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_NULL,
+            BACnetValue::Bool(b) => {
+                value.type_.Boolean = *b;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_BOOLEAN
+            }
+            BACnetValue::Uint(u) => {
+                value.type_.Unsigned_Int = *u;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_UNSIGNED_INT
+            }
+            BACnetValue::Int(i) => {
+                value.type_.Signed_Int = *i;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_SIGNED_INT
+            }
+            BACnetValue::Real(f) => {
+                value.type_.Real = *f;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_REAL
+            }
+            BACnetValue::Double(f) => {
+                value.type_.Double = *f;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_DOUBLE
+            }
+            BACnetValue::String(s) => {
+                let ok = unsafe {
+                    bacnet_sys::characterstring_init(
+                        &mut value.type_.Character_String,
+                        bacnet_sys::BACNET_CHARACTER_STRING_ENCODING_CHARACTER_UTF8 as u8,
+                        s.as_ptr() as *const _,
+                        s.len(),
+                    )
+                };
+                if !ok {
+                    return Err(Error::FailedToEncodeValue);
+                }
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_CHARACTER_STRING
+            }
+            BACnetValue::Bytes(bytes) => {
+                let mut bytes = bytes.clone();
+                let ok = unsafe {
+                    bacnet_sys::octetstring_init(
+                        &mut value.type_.Octet_String,
+                        bytes.as_mut_ptr(),
+                        bytes.len(),
+                    )
+                };
+                if !ok {
+                    return Err(Error::FailedToEncodeValue);
+                }
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_OCTET_STRING
+            }
+            BACnetValue::BitString(bits) => {
+                if bits.len() > (bacnet_sys::MAX_BITSTRING_BYTES * 8) as usize {
+                    return Err(Error::FailedToEncodeValue);
+                }
+                unsafe {
+                    bacnet_sys::bitstring_init(&mut value.type_.Bit_String);
+                    for (i, bit) in bits.iter().enumerate() {
+                        bacnet_sys::bitstring_set_bit(&mut value.type_.Bit_String, i as u8, *bit);
+                    }
+                }
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_BIT_STRING
+            }
+            BACnetValue::Enum(e, _) => {
+                value.type_.Enumerated = *e;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_ENUMERATED
+            }
+            BACnetValue::ObjectId {
+                object_type,
+                object_instance,
+            } => {
+                value.type_.Object_Id.type_ = *object_type;
+                value.type_.Object_Id.instance = *object_instance;
+                bacnet_sys::BACNET_APPLICATION_TAG_BACNET_APPLICATION_TAG_OBJECT_ID
+            }
+            BACnetValue::Array(values) => {
+                for value in values {
+                    value.encode(buf)?;
+                }
+                return Ok(());
+            }
+        };
+        value.tag = tag as u8;
+
+        // Large enough for the longest string plus its tag
+        let mut apdu = [0u8; bacnet_sys::MAX_APDU as usize + 8];
+        let len =
+            unsafe { bacnet_sys::bacapp_encode_application_data(apdu.as_mut_ptr(), &mut value) };
+        if len <= 0 {
+            return Err(Error::FailedToEncodeValue);
+        }
+        buf.extend_from_slice(&apdu[..len as usize]);
+        Ok(())
+    }
+}
+
+// An enumerated value is (de)serialized as its number and, if known, its name
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EnumValue {
+    value: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_enum<S>(value: &u32, name: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serde::Serialize::serialize(
+        &EnumValue {
+            value: *value,
+            name: name.clone(),
+        },
+        serializer,
+    )
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_enum<'de, D>(deserializer: D) -> Result<(u32, Option<String>), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let EnumValue { value, name } = serde::Deserialize::deserialize(deserializer)?;
+    Ok((value, name))
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::BACnetValue;
+    use serde_json::json;
+
+    fn round_trip(value: BACnetValue, expected: serde_json::Value) {
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(json, expected);
+        assert_eq!(serde_json::from_value::<BACnetValue>(json).unwrap(), value);
+    }
+
+    #[test]
+    fn values_carry_their_type() {
+        round_trip(BACnetValue::Null, json!({"type": "null"}));
+        round_trip(
+            BACnetValue::Real(21.5),
+            json!({"type": "real", "value": 21.5}),
+        );
+        round_trip(
+            BACnetValue::String("Outside air".to_string()),
+            json!({"type": "string", "value": "Outside air"}),
+        );
+        round_trip(
+            BACnetValue::BitString(vec![true, false]),
+            json!({"type": "bit-string", "value": [true, false]}),
+        );
+        round_trip(
+            BACnetValue::Array(vec![BACnetValue::Uint(1), BACnetValue::Bool(true)]),
+            json!({"type": "array", "value": [
+                {"type": "uint", "value": 1},
+                {"type": "bool", "value": true},
+            ]}),
+        );
+    }
+
+    #[test]
+    fn enumerations_have_their_number_and_name() {
+        round_trip(
+            BACnetValue::Enum(1, Some("active".to_string())),
+            json!({"type": "enum", "value": {"value": 1, "name": "active"}}),
+        );
+        round_trip(
+            BACnetValue::Enum(300, None),
+            json!({"type": "enum", "value": {"value": 300}}),
+        );
+    }
+
+    #[test]
+    fn object_ids_are_structs() {
+        round_trip(
+            BACnetValue::ObjectId {
+                object_type: 8,
+                object_instance: 1234,
+            },
+            json!({"type": "object-id", "value": {"object_type": 8, "object_instance": 1234}}),
+        );
+    }
+}